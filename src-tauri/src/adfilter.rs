@@ -0,0 +1,259 @@
+use std::fmt;
+use crate::hls::{Key, Map, MediaPlaylist, Segment};
+
+// ============================================================
+// Injected ad removal for HLS media playlists
+// ------------------------------------------------------------
+// Some embed hosts splice ads or promos into the episode as
+// runs of segments between EXT-X-DISCONTINUITY markers. Each
+// run is scored on a few signals (foreign host, short length,
+// a segment cadence unlike the episode's, its own init segment,
+// SCTE-35 cue tags) and removed when the evidence adds up.
+// Resolution isn't visible in a media playlist; a changed init
+// segment is the closest stand-in. Only complete (VOD) playlists
+// are filtered, since dropping segments from a live window would
+// renumber the media sequence between reloads.
+// ============================================================
+
+/// Runs longer than this are never treated as ads
+const MAX_AD_SECONDS: f64 = 120.0;
+/// Runs up to this long count as "short"
+const SHORT_RUN_SECONDS: f64 = 60.0;
+/// Relative difference in median segment duration that counts as a different cadence
+const CADENCE_TOLERANCE: f64 = 0.35;
+/// Evidence needed to remove a run
+const REMOVE_SCORE: u32 = 3;
+/// Never strip more than this share of the playlist
+const MAX_REMOVED_SHARE: f64 = 1.0 / 3.0;
+
+/// One removed run, for the log
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdRun {
+    /// Index of the run's first segment in the original playlist
+    pub first_segment: usize,
+    pub segments: usize,
+    pub duration: f64,
+    pub host: String,
+    pub reasons: Vec<&'static str>,
+}
+
+impl fmt::Display for AdRun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "segments {}..{} ({:.1}s from {}): {}",
+            self.first_segment, self.first_segment + self.segments, self.duration, self.host, self.reasons.join(", "))
+    }
+}
+
+/// Segment index range of each discontinuity-delimited run
+fn runs(segments: &[Segment]) -> Vec<std::ops::Range<usize>> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    for (i, segment) in segments.iter().enumerate().skip(1) {
+        if segment.discontinuity {
+            runs.push(start..i);
+            start = i;
+        }
+    }
+    if start < segments.len() {
+        runs.push(start..segments.len());
+    }
+    runs
+}
+
+/// "a.b.cdn.example" -> "cdn.example"; close enough to group a provider's CDN shards
+fn domain(uri: &str) -> String {
+    let host = url::Url::parse(uri).ok().and_then(|u| u.host_str().map(str::to_string)).unwrap_or_default();
+    if host.parse::<std::net::IpAddr>().is_ok() {
+        return host;
+    }
+    let labels: Vec<&str> = host.split('.').collect();
+    labels[labels.len().saturating_sub(2)..].join(".")
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(f64::total_cmp);
+    values[values.len() / 2]
+}
+
+/// Remove segment runs that look injected. URIs must already be absolute. Returns what
+/// was removed, in playlist order.
+pub fn strip_ads(media: &mut MediaPlaylist) -> Vec<AdRun> {
+    let complete = media.end_list || media.playlist_type.as_deref() == Some("VOD");
+    let runs = runs(&media.segments);
+    if !complete || runs.len() < 2 {
+        return Vec::new();
+    }
+
+    let total: f64 = media.segments.iter().map(|s| s.duration).sum();
+    let domains: Vec<String> = media.segments.iter().map(|s| domain(&s.uri)).collect();
+
+    // The episode's host is the one serving most of the running time
+    let mut by_domain: Vec<(&str, f64)> = Vec::new();
+    for (domain, segment) in domains.iter().zip(&media.segments) {
+        match by_domain.iter_mut().find(|(d, _)| *d == domain.as_str()) {
+            Some((_, seconds)) => *seconds += segment.duration,
+            None => by_domain.push((domain, segment.duration)),
+        }
+    }
+    let main_domain = by_domain.iter().max_by(|a, b| a.1.total_cmp(&b.1)).map(|(d, _)| d.to_string()).unwrap_or_default();
+    let main_cadence = median(media.segments.iter().zip(&domains).filter(|(_, d)| **d == main_domain).map(|(s, _)| s.duration).collect());
+    let main_map = media.segments.iter().find_map(|s| s.map.as_ref()).map(|m| m.uri.clone());
+    let longest = runs.iter().enumerate()
+        .max_by(|(_, a), (_, b)| run_duration(&media.segments[(*a).clone()]).total_cmp(&run_duration(&media.segments[(*b).clone()])))
+        .map(|(i, _)| i);
+
+    let mut removed: Vec<(std::ops::Range<usize>, AdRun)> = Vec::new();
+    let mut removed_seconds = 0.0;
+    for (index, run) in runs.iter().enumerate() {
+        let segments = &media.segments[run.clone()];
+        let duration = run_duration(segments);
+        if Some(index) == longest || duration > MAX_AD_SECONDS {
+            continue;
+        }
+
+        let mut score = 0;
+        let mut reasons = Vec::new();
+        let run_domain = &domains[run.start];
+        if domains[run.clone()].iter().all(|d| d != &main_domain) {
+            score += 2;
+            reasons.push("foreign host");
+        }
+        if duration <= SHORT_RUN_SECONDS && duration < total * 0.1 {
+            score += 1;
+            reasons.push("short run");
+        }
+        let cadence = median(segments.iter().map(|s| s.duration).collect());
+        if main_cadence > 0.0 && ((cadence - main_cadence) / main_cadence).abs() > CADENCE_TOLERANCE {
+            score += 1;
+            reasons.push("segment cadence differs");
+        }
+        if segments.iter().filter_map(|s| s.map.as_ref()).any(|m| Some(&m.uri) != main_map.as_ref()) {
+            score += 1;
+            reasons.push("own init segment");
+        }
+        if segments.iter().flat_map(|s| &s.tags).any(|t| t.contains("CUE-OUT") || t.contains("SCTE35")) {
+            score += 2;
+            reasons.push("ad cue tags");
+        }
+
+        if score >= REMOVE_SCORE && removed_seconds + duration <= total * MAX_REMOVED_SHARE {
+            removed_seconds += duration;
+            removed.push((run.clone(), AdRun {
+                first_segment: run.start,
+                segments: run.len(),
+                duration,
+                host: run_domain.clone(),
+                reasons,
+            }));
+        }
+    }
+
+    if !removed.is_empty() {
+        let keep: Vec<bool> = (0..media.segments.len()).map(|i| !removed.iter().any(|(r, _)| r.contains(&i))).collect();
+        drop_segments(media, &keep);
+    }
+    removed.into_iter().map(|(_, run)| run).collect()
+}
+
+fn run_duration(segments: &[Segment]) -> f64 {
+    segments.iter().map(|s| s.duration).sum()
+}
+
+/// Drop segments, keeping the KEY/MAP in effect for every kept segment what it was
+fn drop_segments(media: &mut MediaPlaylist, keep: &[bool]) {
+    let mut effective_keys: Vec<Key> = Vec::new();
+    let mut effective_map: Option<Map> = None;
+    let mut written_keys: Vec<Key> = Vec::new();
+    let mut written_map: Option<Map> = None;
+    let mut previous_kept = true;
+
+    let segments = std::mem::take(&mut media.segments);
+    for (mut segment, keep) in segments.into_iter().zip(keep) {
+        if !segment.keys.is_empty() {
+            effective_keys = segment.keys.clone();
+        }
+        if segment.map.is_some() {
+            effective_map = segment.map.clone();
+        }
+        if !keep {
+            previous_kept = false;
+            continue;
+        }
+
+        if effective_keys != written_keys {
+            segment.keys = if effective_keys.is_empty() {
+                vec![Key { method: "NONE".to_string(), ..Default::default() }]
+            } else {
+                effective_keys.clone()
+            };
+        }
+        if effective_map != written_map {
+            segment.map = effective_map.clone();
+        }
+        // A gap left by removed segments is still a discontinuity
+        segment.discontinuity |= !previous_kept && !media.segments.is_empty();
+        written_keys = effective_keys.clone();
+        written_map = effective_map.clone();
+        previous_kept = true;
+        media.segments.push(segment);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hls::Playlist;
+
+    fn media(body: &str) -> MediaPlaylist {
+        let mut playlist = Playlist::parse(body).unwrap();
+        playlist.resolve_uris(&url::Url::parse("https://media.example/v/ep1/prog.m3u8").unwrap());
+        match playlist {
+            Playlist::Media(media) => media,
+            Playlist::Master(_) => panic!("not a media playlist"),
+        }
+    }
+
+    fn episode(segments: usize) -> String {
+        (0..segments).map(|i| format!("#EXTINF:10.0,\nmain{}.ts\n", i)).collect()
+    }
+
+    #[test]
+    fn test_strips_foreign_runs_and_keeps_key_state() {
+        let body = format!("#EXTM3U\n#EXT-X-TARGETDURATION:10\n\
+            #EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\"\n{}\
+            #EXT-X-DISCONTINUITY\n#EXT-X-KEY:METHOD=NONE\n#EXTINF:5,\nhttps://ads.example/a1.ts\n#EXTINF:5,\nhttps://ads.example/a2.ts\n\
+            #EXT-X-DISCONTINUITY\n{}#EXT-X-ENDLIST\n", episode(12), episode(12).replace("main", "part2_"));
+        let mut playlist = media(&body);
+        let removed = strip_ads(&mut playlist);
+
+        assert_eq!(removed.len(), 1);
+        assert_eq!((removed[0].first_segment, removed[0].segments, removed[0].host.as_str()), (12, 2, "ads.example"));
+        assert_eq!(removed[0].reasons, vec!["foreign host", "short run", "segment cadence differs"]);
+        assert_eq!(playlist.segments.len(), 24);
+        assert!(playlist.segments.iter().all(|s| s.uri.starts_with("https://media.example/")));
+
+        // The ad's METHOD=NONE still applied to what followed it, so it moves onto that segment
+        let resumed = &playlist.segments[12];
+        assert!(resumed.discontinuity);
+        assert_eq!(resumed.keys[0].method, "NONE");
+        assert_eq!(playlist.segments[0].keys[0].uri.as_deref(), Some("https://media.example/v/ep1/key.bin"));
+        assert!(removed[0].to_string().contains("segments 12..14 (10.0s from ads.example)"));
+    }
+
+    #[test]
+    fn test_leaves_plausible_episode_runs_alone() {
+        // Same-host recap with the episode's cadence, and a live window with a foreign run
+        let body = format!("#EXTM3U\n{}#EXT-X-DISCONTINUITY\n#EXTINF:10,\nrecap.ts\n#EXTINF:10,\nrecap2.ts\n#EXT-X-DISCONTINUITY\n{}#EXT-X-ENDLIST\n",
+            episode(10), episode(10).replace("main", "b"));
+        let mut playlist = media(&body);
+        assert!(strip_ads(&mut playlist).is_empty());
+        assert_eq!(playlist.segments.len(), 22);
+
+        let live = format!("#EXTM3U\n{}#EXT-X-DISCONTINUITY\n#EXTINF:5,\nhttps://ads.example/a1.ts\n#EXT-X-DISCONTINUITY\n{}", episode(10), episode(10));
+        let mut playlist = media(&live);
+        assert!(strip_ads(&mut playlist).is_empty());
+    }
+}
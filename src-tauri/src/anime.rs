@@ -1,25 +1,25 @@
-use reqwest::Client;
 use scraper::{Html, Selector};
 use aes_gcm::{aead::{Aead, KeyInit}, Aes256Gcm, Nonce}; // 0.10.x
 use base64::{Engine as _, engine::general_purpose};
 use serde::{Deserialize, Serialize};
-use regex::Regex;
-use serde_json::json; // Added for proxy headers
-use crate::extractors::{gogaes, unpacker, allanime, hianime};
-use futures::future::join_all;
+use crate::extractors::gogaes;
+use crate::error::{NezukoError, Result};
+use crate::http::{self, RequestExt};
+use crate::ids::{EpisodeId, MediaId};
+use crate::proxy;
+use std::collections::HashMap;
 
 // UPDATED: anitaku.to seems to be the only working domain for this user
 const BASE_URL: &str = "https://anitaku.to";
 const AJAX_URL: &str = "https://ajax.gogo-load.com";
-// Newer UA (Chrome 121)
-const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/121.0.0.0 Safari/537.36";
+const PROVIDER: &str = "anitaku";
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AnimeResult {
 // ... existing struct ...
 
 
-    pub id: String,
+    pub id: MediaId,
     pub title: String,
     pub url: String,
     pub image: String,
@@ -31,16 +31,99 @@ pub struct AnimeResult {
     pub provider: String, // "allanime" or "anitaku" or "awi"
     // Episode count for prioritizing main series over spinoffs
     pub episode_count: Option<i32>,
+    // ID to open for each audio mode ("sub"/"dub"/"raw"), so the UI never builds IDs itself
+    #[serde(default)]
+    pub variant_ids: HashMap<String, MediaId>,
+    // Other names for the show (English / romaji / native) when the provider lists them
+    #[serde(default)]
+    pub alt_titles: Vec<String>,
+    // Set by search merging: every provider entry grouped into this result
+    #[serde(default)]
+    pub matches: Vec<ProviderMatch>,
+    // How sure the merge is that all `matches` are the same show (0.0 - 1.0)
+    #[serde(default)]
+    pub match_confidence: Option<f32>,
+    // Set by search ranking: how well this result fits the query (higher is better)
+    #[serde(default)]
+    pub relevance: Option<f32>,
+    // "TV", "Movie", "OVA"... when the provider's listing shows it
+    #[serde(default)]
+    pub format: Option<String>,
+    // Age rating badge ("18+") when the provider's listing shows it
+    #[serde(default)]
+    pub rating: Option<String>,
+}
+
+/// One provider's entry for a merged search result
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProviderMatch {
+    pub provider: String,
+    pub id: MediaId,
+    pub title: String,
+    pub languages: Vec<String>,
+    pub variant_ids: HashMap<String, MediaId>,
+    pub episode_count: Option<i32>,
+}
+
+/// One page of a filtered catalog search
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SearchPage {
+    pub results: Vec<AnimeResult>,
+    pub page: u32,
+    pub has_next_page: bool,
+    /// Known only when the provider's page navigation shows it
+    #[serde(default)]
+    pub total_pages: Option<u32>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Episode {
-    pub id: String, // e.g. "one-piece-episode-1" or "allanime_id|ep_num"
+    pub id: EpisodeId,
     pub number: f32,
     pub url: String,
+    // Metadata from the provider's list, completed from AniZip when the AniList ID is known
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub synopsis: Option<String>,
+    #[serde(default)]
+    pub thumbnail: Option<String>,
+    #[serde(default)]
+    pub air_date: Option<String>, // "2023-09-29"
+    #[serde(default)]
+    pub runtime: Option<u32>, // minutes
+    #[serde(default)]
+    pub is_filler: bool,
+    #[serde(default)]
+    pub is_recap: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl Episode {
+    /// Episode without metadata (what Anitaku and AllAnime list)
+    pub fn new(id: EpisodeId, number: f32, url: String) -> Self {
+        Episode {
+            id,
+            number,
+            url,
+            title: None,
+            synopsis: None,
+            thumbnail: None,
+            air_date: None,
+            runtime: None,
+            is_filler: false,
+            is_recap: false,
+        }
+    }
+
+    /// Set the title, flagging recap episodes by name ("Recap", "Summary", "Digest")
+    pub fn set_title(&mut self, title: String) {
+        self.is_recap |= crate::merge::title_tokens(&title).iter()
+            .any(|t| matches!(t.as_str(), "recap" | "summary" | "digest"));
+        self.title = Some(title);
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SubtitleTrack {
     pub label: String,
     pub file: String,
@@ -54,7 +137,15 @@ pub struct AudioTrack {
     pub default: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamStatus {
+    #[default]
+    Resolved,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct VideoSource {
     pub url: String,
     pub quality: String, // "default", "backup", "hls"
@@ -63,136 +154,135 @@ pub struct VideoSource {
     pub audio_tracks: Vec<AudioTrack>, // Available audio languages
     pub provider: String, // "Diamond", "Gold", "Silver"
     pub provider_id: String, // "allanime", "gogo", "hianime" - for switching
+    // Server the stream came from ("Luf-Mp4", "HD-1", "Filemoon") for the server picker
+    #[serde(default)]
+    pub server: String,
+    #[serde(default)]
+    pub source_type: String, // "hls", "mp4"
+    #[serde(default)]
+    pub status: StreamStatus,
+    // Why a Failed candidate could not be resolved
+    #[serde(default)]
+    pub error: Option<String>,
+    // Intro/outro/recap/preview ranges, also offered as a "chapters" track in `subtitles`
+    #[serde(default)]
+    pub segments: Vec<SkipSegment>,
+    // Renditions listed by an HLS master, filled by `hls::inspect_source`
+    #[serde(default)]
+    pub variants: Vec<HlsVariant>,
 }
 
-pub async fn search_anime(query: &str) -> Result<Vec<AnimeResult>, String> {
-    
-    // 1. DIAMOND SEARCH: AllAnime
-    // We launch it in parallel with others if desired, or prioritize it.
-    // Let's do parallel for speed.
-    
-    let allanime_search = allanime::search(query, "sub"); // Default to sub search for broad results
-    let anitaku_search = search_anitaku(query);
-    let hianime_search = hianime::search(query);
+/// One rendition of an HLS master playlist
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HlsVariant {
+    pub label: String, // "1080p", or "800k" when the master gives no resolution
+    pub uri: String,   // Absolute upstream URL of the media playlist
+    pub bandwidth: u64,
+    pub average_bandwidth: Option<u64>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub codecs: Option<String>,
+    pub frame_rate: Option<f32>,
+    pub audio_group: Option<String>,
+}
 
-    let (allanime_res, anitaku_res, hianime_res) = futures::join!(allanime_search, anitaku_search, hianime_search);
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SegmentKind {
+    Intro,
+    Outro,
+    Recap,
+    Preview,
+}
 
-    let mut results = Vec::new();
-    
-    // Process Diamond (AllAnime) results
-    if let Ok(r) = allanime_res {
-        println!("DEBUG: AllAnime returned {} results", r.len());
-        for item in r {
-            // Logic to determine available languages based on counts
-            let mut languages = Vec::new();
-            if item.available_episodes.sub > 0 { languages.push("Japanese".to_string()); }
-            if item.available_episodes.dub > 0 { languages.push("English".to_string()); }
-            
-            let is_multi = languages.contains(&"English".to_string()) && languages.contains(&"Japanese".to_string());
-            
-            results.push(AnimeResult {
-                id: format!("allanime:{}", item._id), // Namespace the ID using _id from new struct
-                title: item.name,
-                url: format!("https://allanime.to/anime/{}", item._id),
-                image: item.thumbnail.unwrap_or_default(),
-                release_date: None,
-                language: Some(if is_multi { "Multi".to_string() } else { "Japanese".to_string() }), // Simplify
-                is_multi_audio: is_multi,
-                available_languages: languages,
-                provider: "allanime".to_string(),
-                // Use SUB episode count for prioritizing main series over spinoffs
-                episode_count: Some(item.available_episodes.sub),
-            });
+impl SegmentKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SegmentKind::Intro => "intro",
+            SegmentKind::Outro => "outro",
+            SegmentKind::Recap => "recap",
+            SegmentKind::Preview => "preview",
         }
-    } else if let Err(e) = allanime_res {
-        println!("DEBUG: AllAnime search error: {}", e);
     }
 
-    // Process Gold (HiAnime) results
-    if let Ok(r) = hianime_res {
-        println!("DEBUG: HiAnime returned {} results", r.len());
-        for item in r {
-            let mut languages = Vec::new();
-            // API returns episode counts for sub/dub usually
-            if let Some(eps) = &item.episodes {
-                if eps.sub.unwrap_or(0) > 0 { languages.push("Japanese".to_string()); }
-                if eps.dub.unwrap_or(0) > 0 { languages.push("English".to_string()); }
-            } else {
-                // Default assumption if missing (safe fallback)
-                languages.push("Japanese".to_string());
-            }
-
-            let is_multi = languages.contains(&"English".to_string()) && languages.contains(&"Japanese".to_string());
-            let ep_count = item.episodes.as_ref().and_then(|e| e.sub).unwrap_or(0);
-
-            results.push(AnimeResult {
-                id: format!("hianime:{}", item.id),
-                title: item.name,
-                url: format!("https://hianime.to/{}", item.id),
-                image: item.poster,
-                release_date: None, // Could parse from 'type' or other fields if needed
-                language: Some(if is_multi { "Multi".to_string() } else { "Japanese".to_string() }),
-                is_multi_audio: is_multi,
-                available_languages: languages,
-                provider: "hianime".to_string(),
-                episode_count: Some(ep_count),
-            });
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "intro" => Some(SegmentKind::Intro),
+            "outro" => Some(SegmentKind::Outro),
+            "recap" => Some(SegmentKind::Recap),
+            "preview" => Some(SegmentKind::Preview),
+            _ => None,
         }
-    } else if let Err(e) = hianime_res {
-        println!("DEBUG: HiAnime search error: {}", e);
     }
-    
-    // Process Silver (Anitaku) results
-    if let Ok(r) = anitaku_res { 
-        println!("DEBUG: Anitaku returned {} results", r.len());
-        results.extend(r); 
-    } else if let Err(e) = anitaku_res {
-        println!("DEBUG: Anitaku search error: {}", e);
+
+    /// Chapter title shown by the player
+    pub fn label(&self) -> &'static str {
+        match self {
+            SegmentKind::Intro => "Intro",
+            SegmentKind::Outro => "Outro",
+            SegmentKind::Recap => "Recap",
+            SegmentKind::Preview => "Preview",
+        }
     }
+}
 
-    println!("DEBUG: Total results found: {}", results.len());
-    Ok(results)
+/// A skippable part of the episode, in seconds
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SkipSegment {
+    pub kind: SegmentKind,
+    pub start: f64,
+    pub end: f64,
 }
 
-/// Check if an anime ID exists (Routing to correct provider)
-pub async fn check_anitaku_exists(id: &str) -> Result<bool, String> {
-    if id.starts_with("allanime:") {
-        // Assume true for now or implement existence check
-        return Ok(true);
+impl SkipSegment {
+    /// `None` for the empty/inverted ranges providers send when there is no such segment (0-0)
+    pub fn new(kind: SegmentKind, start: f64, end: f64) -> Option<Self> {
+        (start >= 0.0 && end > start).then_some(SkipSegment { kind, start, end })
     }
-    
-    let client = Client::new();
-    let url = format!("{}/category/{}", BASE_URL, id);
-    println!("DEBUG: Checking if Anitaku anime exists: {}", url);
+}
 
-    let res = client.get(&url)
-        .header("User-Agent", USER_AGENT)
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
-
-    if res.status().is_success() {
-        let html = res.text().await.map_err(|e| e.to_string())?;
-        let exists = html.contains("episode-page") || html.contains("anime_info_episodes");
-        Ok(exists)
-    } else {
-        Ok(false)
+impl VideoSource {
+    /// Re-point proxy URLs (stream and tracks) at this run's proxy, for sources loaded from cache
+    pub fn with_current_proxy(mut self) -> Self {
+        self.url = proxy::rebase(&self.url);
+        for track in self.subtitles.iter_mut() {
+            track.file = proxy::rebase(&track.file);
+        }
+        self
+    }
+
+    /// Placeholder for a candidate that could not be resolved (kept so the UI can list it)
+    pub fn failed(server: &str, provider: &str, provider_id: &str, error: &NezukoError) -> Self {
+        VideoSource {
+            url: String::new(),
+            quality: "unknown".to_string(),
+            is_m3u8: false,
+            subtitles: Vec::new(),
+            audio_tracks: Vec::new(),
+            provider: provider.to_string(),
+            provider_id: provider_id.to_string(),
+            server: server.to_string(),
+            source_type: String::new(),
+            status: StreamStatus::Failed,
+            error: Some(error.to_string()),
+            segments: Vec::new(),
+            variants: Vec::new(),
+        }
     }
 }
 
-pub async fn search_anitaku(query: &str) -> Result<Vec<AnimeResult>, String> {
-    let client = Client::new();
+pub async fn search_anitaku(query: &str) -> Result<Vec<AnimeResult>> {
+    let client = http::client();
     let url = format!("{}/search.html", BASE_URL);
     println!("DEBUG: Searching Anitaku for: '{}'", query);
 
     let res = client.get(&url)
         .query(&[("keyword", query)])
-        .header("User-Agent", USER_AGENT)
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
+        .send_with_retry(PROVIDER)
+        .await?;
+    let res = NezukoError::check_status(PROVIDER, res)?;
 
-    let html_text = res.text().await.map_err(|e| e.to_string())?;
+    let html_text = res.text().await.map_err(|e| NezukoError::from_reqwest(PROVIDER, e))?;
     let document = Html::parse_document(&html_text);
     
     let list_selector = Selector::parse(".last_episodes ul.items li").unwrap();
@@ -213,12 +303,14 @@ pub async fn search_anitaku(query: &str) -> Result<Vec<AnimeResult>, String> {
             let title_text = title.text().collect::<Vec<_>>().join("");
             let image_url = img.value().attr("src").unwrap_or("").to_string();
             let relative_url = link.value().attr("href").unwrap_or("").to_string();
-            let id = relative_url.trim_start_matches("/category/").to_string();
+            let id = MediaId::Anitaku { slug: relative_url.trim_start_matches("/category/").to_string() };
             
             let release_date = release_el.map(|el| el.text().collect::<Vec<_>>().join("").trim().to_string());
 
             let is_dub = title_text.to_lowercase().contains("dub");
             let language = if is_dub { "English".to_string() } else { "Japanese".to_string() };
+            let mode = if is_dub { "dub" } else { "sub" };
+            let variant_ids = HashMap::from([(mode.to_string(), id.clone())]);
             
             results.push(AnimeResult {
                 id,
@@ -231,106 +323,40 @@ pub async fn search_anitaku(query: &str) -> Result<Vec<AnimeResult>, String> {
                 available_languages: vec![language],
                 provider: "anitaku".to_string(),
                 episode_count: None,
+                variant_ids,
+                alt_titles: Vec::new(),
+                matches: Vec::new(),
+                match_confidence: None,
+                relevance: None,
+                format: None,
+                rating: None,
             });
         }
     }
     Ok(results)
 }
 
-pub async fn search_awi(_query: &str) -> Result<Vec<AnimeResult>, String> {
-    // AWI Provider Removed — stub returns empty
-    Ok(Vec::new())
-}
-
-pub async fn get_episodes(anime_id: &str) -> Result<Vec<Episode>, String> {
-    println!("DEBUG get_episodes: ENTRY with ID = '{}'", anime_id);
-    
-    if anime_id.starts_with("allanime:") {
-        let mut real_id = anime_id.trim_start_matches("allanime:").to_string();
-        println!("DEBUG get_episodes: AllAnime detected, real_id = '{}'", real_id);
-        
-        // New format: allanime:showid:sub or allanime:showid:dub
-        // Old format: allanime:showid-dub
-        let mode = if real_id.ends_with(":dub") {
-            real_id = real_id.trim_end_matches(":dub").to_string();
-            println!("DEBUG get_episodes: DUB mode detected (new format), stripped ID = '{}'", real_id);
-            "dub"
-        } else if real_id.ends_with(":sub") {
-            real_id = real_id.trim_end_matches(":sub").to_string();
-            println!("DEBUG get_episodes: SUB mode detected (new format), stripped ID = '{}'", real_id);
-            "sub"
-        } else if real_id.ends_with("-dub") {
-            // Legacy format support
-            real_id = real_id.replace("-dub", "");
-            println!("DEBUG get_episodes: DUB mode detected (legacy format), stripped ID = '{}'", real_id);
-            "dub"
-        } else {
-            println!("DEBUG get_episodes: SUB mode (default)");
-            "sub"
-        };
-
-        println!("DEBUG get_episodes: Calling allanime::get_episodes('{}', '{}')", real_id, mode);
-        match allanime::get_episodes(&real_id, mode).await {
-             Ok(eps) => {
-                 println!("DEBUG get_episodes: AllAnime returned {} episodes", eps.len());
-                 Ok(eps.into_iter().map(|e| Episode {
-                     // Encode state in ID: allanime:id|ep_num|mode
-                     id: format!("allanime:{}|{}|{}", real_id, e.episode_number, mode), 
-                     number: e.episode_number.parse::<f32>().unwrap_or(0.0),
-                     url: e.link,
-                 }).collect())
-             },
-             Err(e) => {
-                 println!("DEBUG get_episodes: AllAnime ERROR: {}", e);
-                 Err(format!("AllAnime Error: {}", e))
-             },
-        }
-    } else if anime_id.starts_with("hianime:") {
-        let real_id = anime_id.trim_start_matches("hianime:");
-        println!("DEBUG get_episodes: HiAnime detected, real_id = '{}'", real_id);
-        
-        match hianime::get_episodes(real_id).await {
-            Ok(eps) => {
-                println!("DEBUG get_episodes: HiAnime returned {} episodes", eps.len());
-                Ok(eps.into_iter().map(|e| Episode {
-                    // hianime:id|ep_id
-                    id: format!("hianime:{}|{}", real_id, e.episode_id),
-                    number: e.number as f32,
-                    url: format!("https://hianime.to/watch/{}", e.episode_id),
-                }).collect())
-            },
-            Err(e) => Err(format!("HiAnime Error: {}", e)),
-        }
-    } else {
-        // Default to Anitaku if not AllAnime
-        // AWI removed
-        get_anitaku_episodes(anime_id).await
-    }
-}
-
-pub async fn get_awi_episodes(anime_id: &str) -> Result<Vec<Episode>, String> {
-    Err("AWI Provider Removed".to_string())
-}
-
-pub async fn get_anitaku_episodes(anime_id: &str) -> Result<Vec<Episode>, String> {
+pub async fn get_anitaku_episodes(anime_id: &str) -> Result<Vec<Episode>> {
     // ... existing logic ...
     println!("DEBUG: Fetching episodes from Anitaku for: {}", anime_id);
-    let client = Client::new();
+    let client = http::client();
     let url = format!("{}/category/{}", BASE_URL, anime_id);
     
     let res = client.get(&url)
-        .header("User-Agent", USER_AGENT)
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
+        .send_with_retry(PROVIDER)
+        .await?;
+    if res.status() == 404 {
+        return Err(NezukoError::not_found(PROVIDER, format!("anime '{}'", anime_id)));
+    }
+    let res = NezukoError::check_status(PROVIDER, res)?;
 
-    let html_text = res.text().await.map_err(|e| e.to_string())?;
+    let html_text = res.text().await.map_err(|e| NezukoError::from_reqwest(PROVIDER, e))?;
 
     // Enum to break dependency on non-Send Html struct across await points
     enum ParsingStep {
         AjaxFetch { movie_id: String, alias: String },
         DirectResult(Vec<Episode>),
-        Failure(String),
+        Failure(NezukoError),
     }
 
     let next_step = {
@@ -355,7 +381,7 @@ pub async fn get_anitaku_episodes(anime_id: &str) -> Result<Vec<Episode>, String
             if !episodes.is_empty() {
                 ParsingStep::DirectResult(episodes)
             } else {
-                ParsingStep::Failure("No episodes found (both AJAX and direct parse failed)".to_string())
+                ParsingStep::Failure(NezukoError::not_found(PROVIDER, "No episodes found (both AJAX and direct parse failed)"))
             }
         }
     }; // document is dropped here, so we are safe to await
@@ -367,12 +393,11 @@ pub async fn get_anitaku_episodes(anime_id: &str) -> Result<Vec<Episode>, String
             let ajax_url = format!("{}/ajax/load-list-episode?ep_start=0&ep_end=9999&id={}&default_ep=0&alias={}", AJAX_URL, movie_id, alias);
             
             let ajax_res = client.get(&ajax_url)
-                .header("User-Agent", USER_AGENT)
-                .send()
-                .await
-                .map_err(|e| e.to_string())?;
+                .send_with_retry(PROVIDER)
+                .await?;
+            let ajax_res = NezukoError::check_status(PROVIDER, ajax_res)?;
 
-            let ajax_html = ajax_res.text().await.map_err(|e| e.to_string())?;
+            let ajax_html = ajax_res.text().await.map_err(|e| NezukoError::from_reqwest(PROVIDER, e))?;
             // New document for AJAX response
             let ajax_doc = Html::parse_document(&ajax_html);
             
@@ -384,7 +409,7 @@ pub async fn get_anitaku_episodes(anime_id: &str) -> Result<Vec<Episode>, String
             println!("DEBUG: Found {} episodes via Direct Parse", episodes.len());
             Ok(episodes)
         },
-        ParsingStep::Failure(msg) => Err(msg),
+        ParsingStep::Failure(err) => Err(err),
     }
 }
 
@@ -395,7 +420,7 @@ fn parse_episodes_from_list(document: &Html, selector_str: &str) -> Vec<Episode>
 
     for element in document.select(&li_selector) {
         let href = element.value().attr("href").unwrap_or("").trim().to_string();
-        let id = href.trim_start_matches('/').trim().to_string();
+        let id = EpisodeId::Anitaku { slug: href.trim_start_matches('/').trim().to_string() };
         
         let ep_text = element.select(&name_selector).next()
             .map(|el| el.text().collect::<Vec<_>>().join(""))
@@ -404,204 +429,28 @@ fn parse_episodes_from_list(document: &Html, selector_str: &str) -> Vec<Episode>
         let ep_clean = ep_text.replace("EP", "").replace("Episode", "").trim().to_string();
         let ep_num = ep_clean.parse::<f32>().unwrap_or(0.0);
 
-        episodes.push(Episode {
-            id,
-            number: ep_num,
-            url: format!("{}{}", BASE_URL, href),
-        });
+        episodes.push(Episode::new(id, ep_num, format!("{}{}", BASE_URL, href)));
     }
     
     episodes.sort_by(|a, b| a.number.partial_cmp(&b.number).unwrap());
     episodes
 }
 
-pub async fn get_stream_source(episode_id: &str) -> Result<VideoSource, String> {
-    if episode_id.starts_with("allanime:") {
-        // ID format: allanime:show_id|ep_num|mode
-        let parts: Vec<&str> = episode_id.split('|').collect();
-        if parts.len() < 3 {
-             return Err("Invalid AllAnime Period ID".to_string());
-        }
-        let show_id = parts[0].trim_start_matches("allanime:");
-        let ep_num = parts[1];
-        let mode = parts[2]; // "sub" or "dub"
-        
-        // Fetch sources
-        match allanime::get_video_sources(show_id, mode, ep_num).await {
-             Ok(sources) => {
-                 // Smart Source Selection (User requested "Best Quality" without UI)
-                 println!("DEBUG: AllAnime sources for {}: {:?}", ep_num, sources);
-
-                 let mut candidates: Vec<&crate::extractors::allanime::AllAnimeSource> = sources.iter().collect();
-
-                 // Priority Scoring: Higher is better
-                 // Apivtwo/Luf-mp4 are HLS with multi-quality. S-mp4 is usually single file.
-                 fn get_score(name: &str, _type: &str) -> i32 {
-                     let n = name.to_lowercase();
-                     if n.contains("apivtwo") { return 10; }
-                     if n.contains("luf-mp4") { return 9; }
-                     if n.contains("default") { return 8; }
-                     if n.contains("fm-hls") { return 7; } // Prioritize Filemoon (HLS)
-                     if n.contains("s-mp4") { return 6; }  // Usually single file, reliable backup
-                     if n.contains("yt-mp4") { return 5; }
-                     if n.contains("sakura") { return 4; }
-                     1
-                 }
-
-                 candidates.sort_by(|a, b| {
-                     let score_a = get_score(&a.source_name, &a.source_type);
-                     let score_b = get_score(&b.source_name, &b.source_type);
-                     score_b.cmp(&score_a) // Descending
-                 });
-
-                 println!("DEBUG: Sorted Candidates: {:?}", candidates.iter().map(|s| &s.source_name).collect::<Vec<_>>());
-
-                 // Try to resolve sources in order until one works
-                 for s in candidates {
-                     println!("DEBUG: Attempting to resolve source: {}", s.source_name);
-                     match allanime::resolve_source_url(&s.source_url).await {
-                         Ok(mut final_url_resolved) => {
-                             println!("DEBUG: Resolved successfully: {}", final_url_resolved);
-                             
-                             // EXTRACTION FOR FILEMOON (Fm-Hls)
-                             if s.source_name.contains("Fm-Hls") || final_url_resolved.contains("bysekoze") || final_url_resolved.contains("filemoon") {
-                                  println!("DEBUG: Filemoon detected, attempting HLS extraction...");
-                                  let client = Client::new();
-                                  if let Ok(extracted) = try_extract_hls(&client, &final_url_resolved).await {
-                                      println!("DEBUG: Extracted HLS: {}", extracted.url);
-                                      final_url_resolved = extracted.url;
-                                  } else {
-                                      println!("DEBUG: HLS Extraction failed for Filemoon");
-                                  }
-                             }
-                             
-                             let final_url = final_url_resolved;
-                             // Check for m3u8 explicitly in the final URL
-                             let is_hls = s.source_url.contains(".m3u8") || s.source_type == "hls" || final_url.contains(".m3u8");
-                             
-                             // Force HLS type if extracted from Filemoon (it's always HLS)
-                             let is_hls = is_hls || (s.source_name.contains("Fm-Hls") && final_url.contains(".m3u8"));
-
-                             let final_stream_url = if is_hls {
-                                 // Route through proxy to handle CORS/Referer for HLS
-                                 let headers = serde_json::json!({
-                                     "Referer": "https://allanime.day",
-                                     "User-Agent": USER_AGENT,
-                                     "Origin": "https://allanime.day"
-                                 }).to_string();
-                                 format!(
-                                     "http://localhost:1420/api/proxy?url={}&headers={}",
-                                     urlencoding::encode(&final_url),
-                                     urlencoding::encode(&headers)
-                                 )
-                             } else {
-                                 final_url
-                             };
-
-                             return Ok(VideoSource {
-                                 url: final_stream_url,
-                                 quality: "default".to_string(), 
-                                 is_m3u8: is_hls,
-                                 subtitles: Vec::new(), 
-                                 audio_tracks: Vec::new(),
-                                 provider: "Diamond".to_string(),
-                                 provider_id: "allanime".to_string(),
-                             });
-                         },
-                         Err(e) => {
-                             println!("DEBUG: Failed to resolve source '{}': {}", s.source_name, e);
-                             // Continue to next candidate
-                         }
-                     }
-                 }
-                 
-                 Err("No playable sources found (all candidates failed resolution)".to_string())
-             },
-             Err(e) => Err(format!("AllAnime Source Error: {}", e)),
-        }
-    } else if episode_id.starts_with("hianime:") {
-        // hianime:id|ep_id  OR  hianime:id|ep_id|category
-        let parts: Vec<&str> = episode_id.split('|').collect();
-        if parts.len() < 2 { return Err("Invalid HiAnime ID format".to_string()); }
-        let ep_id = parts[1];
-        let category = if parts.len() >= 3 { parts[2] } else { "sub" };
-        
-        println!("DEBUG: HiAnime Direct stream: ep_id={}, category={}", ep_id, category);
-        
-        match hianime::get_sources(ep_id, category).await {
-            Ok(data) => {
-                if let Some(src) = data.sources.first() {
-                    // Map subtitle tracks (skip thumbnails)
-                    let mut tracks = Vec::new();
-                    if let Some(ts) = data.tracks {
-                         for t in ts {
-                             if t.kind == "captions" {
-                                 tracks.push(SubtitleTrack {
-                                     label: t.label.unwrap_or("English".to_string()),
-                                     file: t.file,
-                                     kind: "captions".to_string(),
-                                 });
-                             }
-                         }
-                    }
-                    
-                    // Proxy the M3U8 with MegaCloud referer for CORS
-                    let is_hls = src.source_type == "hls" || src.url.contains(".m3u8");
-                    let final_url = if is_hls {
-                        let headers = serde_json::json!({
-                            "Referer": "https://megacloud.blog/",
-                            "Origin": "https://megacloud.blog/",
-                            "User-Agent": USER_AGENT
-                        }).to_string();
-                        format!(
-                            "http://localhost:1420/api/proxy?url={}&headers={}",
-                            urlencoding::encode(&src.url),
-                            urlencoding::encode(&headers)
-                        )
-                    } else {
-                        src.url.clone()
-                    };
-                    
-                    println!("DEBUG: HiAnime Direct stream SUCCESS: {} (hls={})", &src.url[..std::cmp::min(80, src.url.len())], is_hls);
-                    
-                    return Ok(VideoSource {
-                        url: final_url,
-                        quality: "auto".to_string(),
-                        is_m3u8: is_hls,
-                        subtitles: tracks,
-                        audio_tracks: Vec::new(), 
-                        provider: "Gold".to_string(),
-                        provider_id: "hianime".to_string(),
-                    });
-                }
-                Err("No sources in HiAnime response".to_string())
-            },
-            Err(e) => Err(format!("HiAnime Source Error: {}", e)),
-        }
-    } else {
-        // AWI removal from get_stream_source
-        get_anitaku_stream(episode_id).await
-    }
-}
-
-
-pub async fn get_awi_stream(episode_id: &str) -> Result<VideoSource, String> {
-    Err("AWI Provider Removed".to_string())
-}
-
-pub async fn get_anitaku_stream(episode_id: &str) -> Result<VideoSource, String> {
+pub async fn get_anitaku_stream(episode_id: &str) -> Result<VideoSource> {
     // ... existing Anitaku logic ...
     println!("DEBUG: Fetching Anitaku stream for: {}", episode_id);
-    let client = Client::new();
+    let client = http::client();
     let url = format!("{}/{}", BASE_URL, episode_id);
 
     let res = client.get(&url)
-        .header("User-Agent", USER_AGENT)
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
+        .send_with_retry(PROVIDER)
+        .await?;
+    if res.status() == 404 {
+        return Err(NezukoError::not_found(PROVIDER, format!("episode '{}'", episode_id)));
+    }
+    let res = NezukoError::check_status(PROVIDER, res)?;
 
-    let html = res.text().await.map_err(|e| e.to_string())?;
+    let html = res.text().await.map_err(|e| NezukoError::from_reqwest(PROVIDER, e))?;
 
     // Extract embed URL using multiple strategies
     let embed_url = {
@@ -647,12 +496,12 @@ pub async fn get_anitaku_stream(episode_id: &str) -> Result<VideoSource, String>
             (_, Some(s)) if !s.is_empty() => {
                  if s.starts_with("//") { format!("https:{}", s) } else { s }
             },
-            _ => return Err("No valid stream URL found".to_string()),
+            _ => return Err(NezukoError::not_found(PROVIDER, "No valid stream URL found")),
         }
     };
     
     // Try to extract direct HLS from the embed page
-    match try_extract_hls(&client, &embed_url).await {
+    match try_extract_hls(&embed_url).await {
         Ok(video_source) => Ok(video_source),
         Err(e) => {
             println!("DEBUG: HLS extraction failed for embed: {}. Error: {}", embed_url, e);
@@ -665,9 +514,8 @@ pub async fn get_anitaku_stream(episode_id: &str) -> Result<VideoSource, String>
             
             // Fetch the embed page HTML
             if let Ok(embed_res) = client.get(&embed_url)
-                .header("User-Agent", USER_AGENT)
                 .header("Referer", BASE_URL)
-                .send()
+                .send_with_retry(PROVIDER)
                 .await {
                 if let Ok(embed_html) = embed_res.text().await {
                     // Look for encrypted data in script tags or data-value attributes
@@ -697,6 +545,12 @@ pub async fn get_anitaku_stream(episode_id: &str) -> Result<VideoSource, String>
                                                 audio_tracks: Vec::new(),
                                                 provider: "Silver".to_string(),
                                                 provider_id: "anitaku-gogocdn".to_string(),
+                                                server: "GogoCDN".to_string(),
+                                                source_type: if source.contains(".m3u8") { "hls" } else { "mp4" }.to_string(),
+                                                status: StreamStatus::Resolved,
+                                                error: None,
+                                                segments: Vec::new(),
+                                                variants: Vec::new(),
                                             });
                                         }
                                     }
@@ -713,6 +567,12 @@ pub async fn get_anitaku_stream(episode_id: &str) -> Result<VideoSource, String>
                                                 audio_tracks: Vec::new(),
                                                 provider: "Silver".to_string(),
                                                 provider_id: "anitaku-gogocdn".to_string(),
+                                                server: "GogoCDN".to_string(),
+                                                source_type: "hls".to_string(),
+                                                status: StreamStatus::Resolved,
+                                                error: None,
+                                                segments: Vec::new(),
+                                                variants: Vec::new(),
                                             });
                                         }
                                     }
@@ -726,13 +586,15 @@ pub async fn get_anitaku_stream(episode_id: &str) -> Result<VideoSource, String>
                 }
             }
             
-            println!("DEBUG: All extraction methods failed for Anitaku");
-            Err(format!("Stream extraction failed (Filemoon + GogoCDN): {}", e))
+            println!("DEBUG: All extraction methods failed for Anitaku (Filemoon + GogoCDN)");
+            Err(e)
         }
     }
 }
 
 // --- FILEMOON TYPES ---
+const FILEMOON: &str = "filemoon";
+
 #[derive(Deserialize, Debug)]
 pub struct FilemoonPlaybackResponse {
     pub playback: FilemoonPlaybackData,
@@ -755,14 +617,38 @@ pub struct FilemoonDecrypted {
     pub sources: Vec<FilemoonSourceObj>,
 }
 
-pub async fn try_extract_hls(client: &Client, embed_url: &str) -> Result<VideoSource, String> {
+/// Filemoon embed as a proxied HLS source
+pub async fn try_extract_hls(embed_url: &str) -> Result<VideoSource> {
+    let (url, headers) = extract_filemoon(embed_url).await?;
+    Ok(VideoSource {
+        url: proxy::proxy_url(&url, &headers),
+        quality: "Auto".to_string(), // HLS is usually auto
+        is_m3u8: true,
+        subtitles: Vec::new(),
+        audio_tracks: Vec::new(),
+        provider: "Gold (Anitaku)".to_string(),
+        provider_id: "gogo".to_string(), // Gold = Gogo/Anitaku
+        server: "Filemoon".to_string(),
+        source_type: "hls".to_string(),
+        status: StreamStatus::Resolved,
+        error: None,
+        segments: Vec::new(),
+        variants: Vec::new(),
+    })
+}
+
+/// Upstream HLS URL behind a Filemoon embed and the headers (JSON) it must be fetched
+/// with. Not proxied, so callers wrap it exactly once.
+pub async fn extract_filemoon(embed_url: &str) -> Result<(String, String)> {
     println!("DEBUG: Filemoon extraction for {}", embed_url);
 
     // 1. Extract ID from URL
-    let parsed_url = reqwest::Url::parse(embed_url).map_err(|e| e.to_string())?;
-    let domain = parsed_url.host_str().ok_or("No host in URL")?;
+    let parsed_url = reqwest::Url::parse(embed_url).map_err(|e| NezukoError::parse(FILEMOON, e))?;
+    let domain = parsed_url.host_str().ok_or_else(|| NezukoError::parse(FILEMOON, "No host in URL"))?;
     
-    let path_segments: Vec<&str> = parsed_url.path_segments().ok_or("No path segments")?.collect();
+    let path_segments: Vec<&str> = parsed_url.path_segments()
+        .ok_or_else(|| NezukoError::parse(FILEMOON, "No path segments"))?
+        .collect();
     // Usually /e/{id} or sometimes /v/{id} or just /{id}
     // We look for the ID. Usually the last segment if not empty.
     let video_id = if path_segments.len() >= 2 && (path_segments[0] == "e" || path_segments[0] == "v") {
@@ -770,7 +656,7 @@ pub async fn try_extract_hls(client: &Client, embed_url: &str) -> Result<VideoSo
     } else if !path_segments.is_empty() {
         path_segments[0] 
     } else {
-        return Err("Could not extract video ID".to_string());
+        return Err(NezukoError::parse(FILEMOON, "Could not extract video ID"));
     };
 
     println!("DEBUG: Resolved Filemoon ID: {}", video_id);
@@ -779,98 +665,72 @@ pub async fn try_extract_hls(client: &Client, embed_url: &str) -> Result<VideoSo
     let api_url = format!("https://{}/api/videos/{}/embed/playback", domain, video_id);
     println!("DEBUG: Fetching API: {}", api_url);
 
-    let resp = client.get(&api_url)
+    let resp = http::client().get(&api_url)
         .header("Referer", embed_url)
-        .header("User-Agent", USER_AGENT)
         .header("Origin", format!("https://{}", domain))
         .header("Accept", "application/json, text/javascript, */*; q=0.01")
         .header("X-Requested-With", "XMLHttpRequest")
         .header("Sec-Fetch-Dest", "empty")
         .header("Sec-Fetch-Mode", "cors")
         .header("Sec-Fetch-Site", "same-origin")
-        .send()
-        .await
-        .map_err(|e| format!("Request failed: {}", e))?;
-
-
+        .send_with_retry(FILEMOON)
+        .await?;
+    let resp = NezukoError::check_status(FILEMOON, resp)?;
 
-    if !resp.status().is_success() {
-        let status = resp.status();
-        let text = resp.text().await.unwrap_or_default();
-        return Err(format!("API returned status {}: {}", status, text));
-    }
-
-    let json_text = resp.text().await.map_err(|e| format!("Failed to get text: {}", e))?;
+    let json_text = resp.text().await.map_err(|e| NezukoError::from_reqwest(FILEMOON, e))?;
     // println!("DEBUG: Raw JSON: {}", json_text);
 
     let data: FilemoonPlaybackResponse = serde_json::from_str(&json_text)
-        .map_err(|e| format!("Failed to parse JSON: {}", e))?;
+        .map_err(|e| NezukoError::parse(FILEMOON, format!("playback JSON: {}", e)))?;
 
     // 3. Reconstruct Key
     let mut combined_key = Vec::new();
     for part in &data.playback.key_parts {
        let part_bytes = general_purpose::URL_SAFE_NO_PAD
            .decode(part)
-           .map_err(|e| format!("Base64 decode key part failed: {}", e))?;
+           .map_err(|e| NezukoError::decrypt(FILEMOON, format!("Base64 decode key part failed: {}", e)))?;
        combined_key.extend(part_bytes);
     }
 
     if combined_key.len() != 32 {
-        return Err(format!("Invalid key length: {}. Expected 32 for AES-256.", combined_key.len()));
+        return Err(NezukoError::decrypt(FILEMOON, format!("Invalid key length: {}. Expected 32 for AES-256.", combined_key.len())));
     }
 
     // 4. Decrypt
     let iv_bytes = general_purpose::URL_SAFE_NO_PAD
         .decode(&data.playback.iv)
-        .map_err(|e| format!("Base64 decode IV failed: {}", e))?;
+        .map_err(|e| NezukoError::decrypt(FILEMOON, format!("Base64 decode IV failed: {}", e)))?;
     
     let payload_bytes = general_purpose::URL_SAFE_NO_PAD
         .decode(&data.playback.payload)
-        .map_err(|e| format!("Base64 decode Payload failed: {}", e))?;
+        .map_err(|e| NezukoError::decrypt(FILEMOON, format!("Base64 decode Payload failed: {}", e)))?;
 
     let cipher = Aes256Gcm::new_from_slice(&combined_key)
-        .map_err(|e| format!("Cipher init failed: {}", e))?;
+        .map_err(|e| NezukoError::decrypt(FILEMOON, format!("Cipher init failed: {}", e)))?;
 
     let nonce = Nonce::from_slice(&iv_bytes); // 96-bits
 
     let plaintext = cipher.decrypt(nonce, payload_bytes.as_ref())
-        .map_err(|e| format!("Decryption failed (GCM): {}", e))?;
+        .map_err(|e| NezukoError::decrypt(FILEMOON, format!("GCM: {}", e)))?;
 
     let plaintext_str = String::from_utf8(plaintext)
-        .map_err(|e| format!("Decrypted data is not UTF-8: {}", e))?;
+        .map_err(|e| NezukoError::decrypt(FILEMOON, format!("Decrypted data is not UTF-8: {}", e)))?;
 
     // println!("DEBUG: Decrypted JSON: {}", plaintext_str);
 
     let decrypted: FilemoonDecrypted = serde_json::from_str(&plaintext_str)
-        .map_err(|e| format!("Failed to parse decrypted JSON: {}", e))?;
+        .map_err(|e| NezukoError::parse(FILEMOON, format!("decrypted JSON: {}", e)))?;
 
-    // 5. Return VideoSource
+    // 5. Upstream URL with the Referer/Origin the CDN checks
     if let Some(src) = decrypted.sources.first() {
-        // Construct Proxy URL to handle Referer/CORS
         let headers = serde_json::json!({
             "Referer": embed_url,
-            "User-Agent": "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
             "Origin": format!("https://{}", domain)
         }).to_string();
-
-        let proxy_url = format!(
-            "http://localhost:1420/api/proxy?url={}&headers={}",
-            urlencoding::encode(&src.url),
-            urlencoding::encode(&headers)
-        );
-
-        return Ok(VideoSource {
-            url: proxy_url,
-            quality: "Auto".to_string(), // HLS is usually auto
-            is_m3u8: true,
-            subtitles: Vec::new(),
-            audio_tracks: Vec::new(),
-            provider: "Gold (Anitaku)".to_string(),
-            provider_id: "gogo".to_string(), // Gold = Gogo/Anitaku
-        });
+        return Ok((src.url.clone(), headers));
     }
 
-    Err("No sources found in decrypted payload".to_string())
+    Err(NezukoError::not_found(FILEMOON, "No sources found in decrypted payload"))
 }
 
 
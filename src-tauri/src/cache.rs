@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use md5::{Digest, Md5};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Entry kinds, the first part of every key; each can be cleared on its own
+pub const SEARCH: &str = "search";
+pub const EPISODES: &str = "episodes";
+pub const STREAM: &str = "stream";
+pub const CANDIDATES: &str = "candidates";
+pub const SERVERS: &str = "servers";
+pub const MAPPINGS: &str = "mappings";
+pub const KINDS: &[&str] = &[SEARCH, EPISODES, STREAM, CANDIDATES, SERVERS, MAPPINGS];
+
+pub const SEARCH_TTL: Duration = Duration::from_secs(10 * 60);
+pub const EPISODES_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+pub const MAPPINGS_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+pub const SERVERS_TTL: Duration = Duration::from_secs(30 * 60);
+/// Stream URLs without a visible expiry
+const STREAM_DEFAULT_TTL: Duration = Duration::from_secs(30 * 60);
+const STREAM_MAX_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+/// Stop serving a signed URL this long before the CDN would reject it
+const STREAM_EXPIRY_MARGIN: u64 = 120;
+
+/// One cached response on disk (`<cache dir>/<md5 of key>.json`)
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    key: String,
+    kind: String,
+    stored_at: u64,
+    expires_at: u64,
+    value: serde_json::Value,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct CacheStats {
+    pub dir: String,
+    pub entries: usize,
+    pub expired: usize,
+    pub bytes: u64,
+    pub by_kind: HashMap<String, usize>,
+}
+
+/// Persistent JSON cache in the app data directory.
+/// Keys look like `<kind>:<provider>:<request>`, e.g. `episodes:hianime:v1:hianime:frieren-18542`.
+pub struct DiskCache {
+    dir: PathBuf,
+}
+
+impl DiskCache {
+    pub fn new(dir: PathBuf) -> Self {
+        if let Err(e) = fs::create_dir_all(&dir) {
+            println!("DEBUG: Could not create cache dir {}: {}", dir.display(), e);
+        }
+        Self { dir }
+    }
+
+    pub fn key(kind: &str, provider: &str, request: &str) -> String {
+        format!("{}:{}:{}", kind, provider, request)
+    }
+
+    fn path_for(&self, key: &str) -> PathBuf {
+        let digest = Md5::digest(key.as_bytes());
+        self.dir.join(format!("{}.json", hex::encode(digest)))
+    }
+
+    /// Fresh value for `key`, if any. Expired or unreadable entries are deleted.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let path = self.path_for(key);
+        let entry = read_entry(&path)?;
+
+        if entry.key != key || entry.expires_at <= now_secs() {
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+
+        match serde_json::from_value(entry.value) {
+            Ok(value) => {
+                println!("DEBUG: Cache hit {}", key);
+                Some(value)
+            },
+            Err(_) => {
+                let _ = fs::remove_file(&path);
+                None
+            },
+        }
+    }
+
+    pub fn put<T: Serialize>(&self, key: &str, value: &T, ttl: Duration) {
+        if ttl.is_zero() {
+            return;
+        }
+        let Ok(value) = serde_json::to_value(value) else { return };
+
+        let now = now_secs();
+        let entry = CacheEntry {
+            key: key.to_string(),
+            kind: key.split(':').next().unwrap_or("").to_string(),
+            stored_at: now,
+            expires_at: now + ttl.as_secs(),
+            value,
+        };
+
+        // Write then rename so a concurrent reader never sees half a file
+        let path = self.path_for(key);
+        let tmp = path.with_extension("tmp");
+        let written = serde_json::to_vec(&entry)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| fs::write(&tmp, bytes).map_err(|e| e.to_string()))
+            .and_then(|_| fs::rename(&tmp, &path).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            println!("DEBUG: Cache write failed for {}: {}", key, e);
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let mut stats = CacheStats { dir: self.dir.display().to_string(), ..Default::default() };
+        let now = now_secs();
+
+        for path in self.entry_paths() {
+            let Some(entry) = read_entry(&path) else { continue };
+            stats.entries += 1;
+            stats.bytes += fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            if entry.expires_at <= now {
+                stats.expired += 1;
+            }
+            *stats.by_kind.entry(entry.kind).or_insert(0) += 1;
+        }
+        stats
+    }
+
+    /// Delete every entry, or only those of one kind (one of `KINDS`: "search", "episodes", "stream",
+    /// "candidates", "servers", "mappings"). Returns how many files were removed.
+    pub fn clear(&self, kind: Option<&str>) -> usize {
+        let mut removed = 0;
+        for path in self.entry_paths() {
+            let matches = match kind {
+                None => true,
+                Some(k) => read_entry(&path).map(|e| e.kind == k).unwrap_or(true),
+            };
+            if matches && fs::remove_file(&path).is_ok() {
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    fn entry_paths(&self) -> Vec<PathBuf> {
+        let Ok(dir) = fs::read_dir(&self.dir) else { return Vec::new() };
+        dir.filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+            .collect()
+    }
+}
+
+fn read_entry(path: &Path) -> Option<CacheEntry> {
+    let bytes = fs::read(path).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// How long a resolved stream URL can be reused. Signed CDN links carry a unix
+/// expiry in the query (`expires=`, `exp=`, `e=`); proxied URLs are unwrapped first.
+pub fn stream_ttl(url: &str) -> Duration {
+    stream_ttl_at(url, now_secs())
+}
+
+fn stream_ttl_at(url: &str, now: u64) -> Duration {
+    let Ok(parsed) = reqwest::Url::parse(url) else { return STREAM_DEFAULT_TTL };
+
+    let mut expiry = None;
+    for (name, value) in parsed.query_pairs() {
+        if name == "url" {
+            return stream_ttl_at(&value, now);
+        }
+        if matches!(name.to_ascii_lowercase().as_str(), "expires" | "expire" | "exp" | "e") {
+            // Only accept plausible unix timestamps (after 2020)
+            expiry = value.parse::<u64>().ok().filter(|ts| *ts > 1_577_836_800);
+        }
+    }
+
+    match expiry {
+        Some(ts) => Duration::from_secs(ts.saturating_sub(now + STREAM_EXPIRY_MARGIN)).min(STREAM_MAX_TTL),
+        None => STREAM_DEFAULT_TTL,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_cache(name: &str) -> DiskCache {
+        let dir = std::env::temp_dir().join(format!("nezuko-cache-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        DiskCache::new(dir)
+    }
+
+    #[test]
+    fn test_put_get_and_clear_by_kind() {
+        let cache = temp_cache("roundtrip");
+        let search_key = DiskCache::key("search", "allanime", "frieren");
+        let episodes_key = DiskCache::key("episodes", "hianime", "v1:hianime:frieren-18542");
+
+        cache.put(&search_key, &vec!["a".to_string(), "b".to_string()], SEARCH_TTL);
+        cache.put(&episodes_key, &vec![1, 2, 3], EPISODES_TTL);
+
+        assert_eq!(cache.get::<Vec<String>>(&search_key), Some(vec!["a".to_string(), "b".to_string()]));
+        assert_eq!(cache.get::<Vec<i32>>(&episodes_key), Some(vec![1, 2, 3]));
+
+        let stats = cache.stats();
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.by_kind.get("search"), Some(&1));
+
+        assert_eq!(cache.clear(Some("search")), 1);
+        assert!(cache.get::<Vec<String>>(&search_key).is_none());
+        assert!(cache.get::<Vec<i32>>(&episodes_key).is_some());
+        assert_eq!(cache.clear(None), 1);
+
+        let _ = fs::remove_dir_all(&cache.dir);
+    }
+
+    #[test]
+    fn test_expired_entries_are_dropped() {
+        let cache = temp_cache("expiry");
+        let key = DiskCache::key("stream", "allanime", "v1:allanime:abc:sub:1");
+        cache.put(&key, &"url", Duration::from_secs(60));
+
+        // Rewrite the entry as already expired
+        let path = cache.path_for(&key);
+        let mut entry = read_entry(&path).unwrap();
+        entry.expires_at = now_secs() - 1;
+        fs::write(&path, serde_json::to_vec(&entry).unwrap()).unwrap();
+
+        assert!(cache.get::<String>(&key).is_none());
+        assert!(!path.exists());
+
+        let _ = fs::remove_dir_all(&cache.dir);
+    }
+
+    #[test]
+    fn test_stream_ttl_from_signed_url() {
+        let now = 1_700_000_000;
+        let signed = format!("https://cdn.example.com/master.m3u8?token=x&expires={}", now + 3600);
+        assert_eq!(stream_ttl_at(&signed, now), Duration::from_secs(3600 - STREAM_EXPIRY_MARGIN));
+
+        let proxied = format!(
+            "http://localhost:1420/api/proxy?url={}&headers=%7B%7D",
+            urlencoding::encode(&signed)
+        );
+        assert_eq!(stream_ttl_at(&proxied, now), Duration::from_secs(3600 - STREAM_EXPIRY_MARGIN));
+
+        let nearly_expired = format!("https://cdn.example.com/a.mp4?e={}", now + 30);
+        assert_eq!(stream_ttl_at(&nearly_expired, now), Duration::ZERO);
+
+        assert_eq!(stream_ttl_at("https://cdn.example.com/a.m3u8", now), STREAM_DEFAULT_TTL);
+        assert_eq!(stream_ttl_at(&format!("https://x.io/a?exp={}", now + 86_400 * 30), now), STREAM_MAX_TTL);
+    }
+}
@@ -0,0 +1,109 @@
+use crate::anime::{SegmentKind, SkipSegment, SubtitleTrack};
+use crate::proxy;
+
+// ============================================================
+// Skip segments as a WebVTT chapters track
+// ------------------------------------------------------------
+// Segments travel in the track URL itself
+// ("/chapters?segments=intro:31.5-120,outro:1310-1400") so the
+// proxy can render the VTT without keeping any state. Gaps
+// between segments become "Episode" chapters.
+// ============================================================
+
+/// Chapters track for a source's segments, `None` when there are none
+pub fn chapters_track(segments: &[SkipSegment]) -> Option<SubtitleTrack> {
+    if segments.is_empty() {
+        return None;
+    }
+    Some(SubtitleTrack {
+        label: "Chapters".to_string(),
+        file: format!("{}?segments={}", proxy::endpoint("chapters"), urlencoding::encode(&encode_segments(segments))),
+        kind: "chapters".to_string(),
+    })
+}
+
+/// "intro:31.5-120,outro:1310-1400"
+pub fn encode_segments(segments: &[SkipSegment]) -> String {
+    segments.iter()
+        .map(|s| format!("{}:{}-{}", s.kind.as_str(), s.start, s.end))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Inverse of `encode_segments`; malformed or empty entries are dropped
+pub fn parse_segments(encoded: &str) -> Vec<SkipSegment> {
+    encoded.split(',')
+        .filter_map(|entry| {
+            let (kind, range) = entry.trim().split_once(':')?;
+            let (start, end) = range.split_once('-')?;
+            SkipSegment::new(SegmentKind::parse(kind)?, start.parse().ok()?, end.parse().ok()?)
+        })
+        .collect()
+}
+
+/// WebVTT chapters covering the segments, in time order
+pub fn to_webvtt(segments: &[SkipSegment]) -> String {
+    let mut segments = segments.to_vec();
+    segments.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+    let mut vtt = String::from("WEBVTT\n");
+    let mut cursor = 0.0;
+    let mut cue = |start: f64, end: f64, label: &str| {
+        vtt.push_str(&format!("\n{} --> {}\n{}\n", timestamp(start), timestamp(end), label));
+    };
+
+    for segment in &segments {
+        // Overlapping segments are clipped to start where the previous one ended
+        let start = segment.start.max(cursor);
+        if segment.end <= start {
+            continue;
+        }
+        if start > cursor {
+            cue(cursor, start, "Episode");
+        }
+        cue(start, segment.end, segment.kind.label());
+        cursor = segment.end;
+    }
+    vtt
+}
+
+/// HH:MM:SS.mmm
+fn timestamp(seconds: f64) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!("{:02}:{:02}:{:02}.{:03}", millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60, millis % 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segments_round_trip_through_url() {
+        let segments = vec![
+            SkipSegment::new(SegmentKind::Intro, 31.5, 120.0).unwrap(),
+            SkipSegment::new(SegmentKind::Outro, 1310.0, 1400.0).unwrap(),
+        ];
+        assert_eq!(encode_segments(&segments), "intro:31.5-120,outro:1310-1400");
+        assert_eq!(parse_segments("intro:31.5-120,outro:1310-1400"), segments);
+        assert_eq!(parse_segments("intro:90-10,bogus:1-2,preview:x-3"), Vec::new());
+
+        let track = chapters_track(&segments).unwrap();
+        assert_eq!(track.kind, "chapters");
+        assert!(track.file.ends_with("?segments=intro%3A31.5-120%2Coutro%3A1310-1400"));
+        assert!(chapters_track(&[]).is_none());
+    }
+
+    #[test]
+    fn test_webvtt_fills_gaps_between_segments() {
+        let segments = vec![
+            SkipSegment::new(SegmentKind::Outro, 1310.0, 1400.0).unwrap(),
+            SkipSegment::new(SegmentKind::Recap, 0.0, 45.0).unwrap(),
+            SkipSegment::new(SegmentKind::Intro, 40.0, 130.25).unwrap(),
+        ];
+        assert_eq!(to_webvtt(&segments), "WEBVTT\n\
+            \n00:00:00.000 --> 00:00:45.000\nRecap\n\
+            \n00:00:45.000 --> 00:02:10.250\nIntro\n\
+            \n00:02:10.250 --> 00:21:50.000\nEpisode\n\
+            \n00:21:50.000 --> 00:23:20.000\nOutro\n");
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt::Display;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+/// Error type shared by every extractor and Tauri command.
+/// Each variant carries the provider it came from ("allanime", "hianime", "anitaku", "anizip", ...).
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum NezukoError {
+    /// Connection refused, DNS failure, timeout, TLS error...
+    #[error("{provider}: network error: {message}")]
+    Network { provider: String, message: String },
+
+    /// Upstream answered with a non-success status
+    #[error("{provider}: HTTP {status} from {url}")]
+    HttpStatus { provider: String, status: u16, url: String },
+
+    /// Upstream answered, but behind a Cloudflare challenge / block page
+    #[error("{provider}: blocked by Cloudflare (HTTP {status})")]
+    Blocked { provider: String, status: u16 },
+
+    /// Unexpected HTML/JSON shape
+    #[error("{provider}: parse error: {message}")]
+    Parse { provider: String, message: String },
+
+    /// Stream payload could not be decrypted (keys rotated, bad padding...)
+    #[error("{provider}: decryption failed: {message}")]
+    Decrypt { provider: String, message: String },
+
+    /// Show, episode or source does not exist on this provider
+    #[error("{provider}: not found: {message}")]
+    NotFound { provider: String, message: String },
+
+    /// Request is valid but this provider cannot serve it (wrong ID type, disabled provider...)
+    #[error("{provider}: unsupported: {message}")]
+    Unsupported { provider: String, message: String },
+
+    /// Skipped without a request because the provider's circuit breaker is open
+    #[error("{provider}: temporarily unavailable: {message}")]
+    Unavailable { provider: String, message: String },
+}
+
+pub type Result<T> = std::result::Result<T, NezukoError>;
+
+impl NezukoError {
+    pub fn network(provider: &str, message: impl Display) -> Self {
+        NezukoError::Network { provider: provider.to_string(), message: message.to_string() }
+    }
+
+    pub fn http_status(provider: &str, status: u16, url: impl Display) -> Self {
+        NezukoError::HttpStatus { provider: provider.to_string(), status, url: url.to_string() }
+    }
+
+    pub fn parse(provider: &str, message: impl Display) -> Self {
+        NezukoError::Parse { provider: provider.to_string(), message: message.to_string() }
+    }
+
+    pub fn decrypt(provider: &str, message: impl Display) -> Self {
+        NezukoError::Decrypt { provider: provider.to_string(), message: message.to_string() }
+    }
+
+    pub fn not_found(provider: &str, message: impl Display) -> Self {
+        NezukoError::NotFound { provider: provider.to_string(), message: message.to_string() }
+    }
+
+    pub fn unsupported(provider: &str, message: impl Display) -> Self {
+        NezukoError::Unsupported { provider: provider.to_string(), message: message.to_string() }
+    }
+
+    pub fn unavailable(provider: &str, message: impl Display) -> Self {
+        NezukoError::Unavailable { provider: provider.to_string(), message: message.to_string() }
+    }
+
+    /// Classify a reqwest error: body decode failures are parse errors, everything else is network.
+    pub fn from_reqwest(provider: &str, err: reqwest::Error) -> Self {
+        if err.is_decode() {
+            return NezukoError::parse(provider, err);
+        }
+        if let Some(status) = err.status() {
+            let url = err.url().map(|u| u.to_string()).unwrap_or_default();
+            return NezukoError::http_status(provider, status.as_u16(), url);
+        }
+        NezukoError::network(provider, err)
+    }
+
+    /// Turn a non-success response into `HttpStatus` (or `Blocked` for Cloudflare challenges).
+    pub fn check_status(provider: &str, res: reqwest::Response) -> Result<reqwest::Response> {
+        let status = res.status();
+        if status.is_success() {
+            return Ok(res);
+        }
+
+        let headers = res.headers();
+        let is_cloudflare = headers.contains_key("cf-mitigated")
+            || (headers.contains_key("cf-ray") && matches!(status.as_u16(), 403 | 429 | 503));
+        if is_cloudflare {
+            return Err(NezukoError::Blocked { provider: provider.to_string(), status: status.as_u16() });
+        }
+
+        Err(NezukoError::http_status(provider, status.as_u16(), res.url()))
+    }
+
+    pub fn provider(&self) -> &str {
+        match self {
+            NezukoError::Network { provider, .. }
+            | NezukoError::HttpStatus { provider, .. }
+            | NezukoError::Blocked { provider, .. }
+            | NezukoError::Parse { provider, .. }
+            | NezukoError::Decrypt { provider, .. }
+            | NezukoError::NotFound { provider, .. }
+            | NezukoError::Unsupported { provider, .. }
+            | NezukoError::Unavailable { provider, .. } => provider,
+        }
+    }
+
+    /// Stable machine-readable tag for the frontend
+    pub fn kind(&self) -> &'static str {
+        match self {
+            NezukoError::Network { .. } => "network",
+            NezukoError::HttpStatus { .. } => "http_status",
+            NezukoError::Blocked { .. } => "blocked",
+            NezukoError::Parse { .. } => "parse",
+            NezukoError::Decrypt { .. } => "decrypt",
+            NezukoError::NotFound { .. } => "not_found",
+            NezukoError::Unsupported { .. } => "unsupported",
+            NezukoError::Unavailable { .. } => "unavailable",
+        }
+    }
+
+    /// Whether trying the same request again later might succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            NezukoError::Network { .. } | NezukoError::Unavailable { .. } => true,
+            NezukoError::HttpStatus { status, .. } => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+/// Serialised for the Tauri bridge as
+/// `{ "kind": "...", "provider": "...", "message": "...", "status": 503, "retryable": true }`
+impl Serialize for NezukoError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let status = match self {
+            NezukoError::HttpStatus { status, .. } | NezukoError::Blocked { status, .. } => Some(*status),
+            _ => None,
+        };
+
+        let mut state = serializer.serialize_struct("NezukoError", 5)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("provider", self.provider())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("status", &status)?;
+        state.serialize_field("retryable", &self.is_retryable())?;
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serializes_for_frontend() {
+        let err = NezukoError::http_status("hianime", 503, "https://hianime.bz/search");
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["kind"], "http_status");
+        assert_eq!(json["provider"], "hianime");
+        assert_eq!(json["status"], 503);
+        assert_eq!(json["retryable"], true);
+        assert_eq!(json["message"], "hianime: HTTP 503 from https://hianime.bz/search");
+
+        let json = serde_json::to_value(NezukoError::not_found("allanime", "episode 12")).unwrap();
+        assert_eq!(json["kind"], "not_found");
+        assert_eq!(json["status"], serde_json::Value::Null);
+        assert_eq!(json["retryable"], false);
+    }
+
+    #[test]
+    fn test_retryable_classification() {
+        assert!(NezukoError::network("anitaku", "timed out").is_retryable());
+        assert!(NezukoError::http_status("anitaku", 429, "u").is_retryable());
+        assert!(!NezukoError::http_status("anitaku", 404, "u").is_retryable());
+        assert!(!NezukoError::Blocked { provider: "anitaku".to_string(), status: 403 }.is_retryable());
+        assert!(!NezukoError::decrypt("hianime", "bad padding").is_retryable());
+    }
+}
@@ -0,0 +1,50 @@
+use async_trait::async_trait;
+use crate::error::{NezukoError, Result};
+use crate::anime::{self, AnimeResult, Episode, VideoSource};
+use crate::extractors::mapping::ProviderMappings;
+use crate::ids::{EpisodeId, MediaId, TranslationType};
+use crate::provider::{Provider, ProviderInfo};
+
+/// Silver provider. The scraping itself lives in `anime.rs` (search/episodes/stream for anitaku.to).
+pub struct AnitakuProvider;
+
+#[async_trait]
+impl Provider for AnitakuProvider {
+    fn info(&self) -> ProviderInfo {
+        ProviderInfo {
+            id: "anitaku",
+            name: "Anitaku",
+            tier: "Silver",
+        }
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<AnimeResult>> {
+        anime::search_anitaku(query).await
+    }
+
+    async fn episodes(&self, anime_id: &MediaId) -> Result<Vec<Episode>> {
+        let MediaId::Anitaku { slug } = anime_id else {
+            return Err(NezukoError::unsupported("anitaku", format!("Not an Anitaku ID: {}", anime_id)));
+        };
+        anime::get_anitaku_episodes(slug).await
+    }
+
+    /// Gogoanime slugs carry the dub as a separate show ("<slug>-dub")
+    fn media_id_from_mappings(&self, mappings: &ProviderMappings, language: TranslationType) -> Option<MediaId> {
+        let slug = mappings.gogoanime_id.clone()?;
+        let slug = match language {
+            TranslationType::Sub => slug,
+            TranslationType::Dub if slug.ends_with("-dub") => slug,
+            TranslationType::Dub => format!("{}-dub", slug),
+            TranslationType::Raw => return None,
+        };
+        Some(MediaId::Anitaku { slug })
+    }
+
+    async fn stream(&self, episode_id: &EpisodeId) -> Result<VideoSource> {
+        let EpisodeId::Anitaku { slug } = episode_id else {
+            return Err(NezukoError::unsupported("anitaku", format!("Not an Anitaku episode ID: {}", episode_id)));
+        };
+        anime::get_anitaku_stream(slug).await
+    }
+}
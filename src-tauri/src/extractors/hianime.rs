@@ -1,12 +1,24 @@
-use reqwest::Client;
+use std::collections::HashMap;
 use scraper::{Html, Selector};
 use serde::{Deserialize, Serialize};
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
 use crate::extractors::gogaes;
-
+use crate::error::{NezukoError, Result};
+use crate::http::{self, RequestExt};
+use crate::anime::{AnimeResult, Episode, SearchPage, SegmentKind, SkipSegment, StreamStatus, SubtitleTrack, VideoSource};
+use crate::chapters;
+use crate::ids::{EpisodeId, MediaId, TranslationType};
+use crate::extractors::mapping::ProviderMappings;
+use crate::provider::{Provider, ProviderInfo};
+use crate::proxy;
+
+const PROVIDER: &str = "hianime";
 const HIANIME_BASE: &str = "https://hianime.bz";
 const MEGACLOUD_BASE: &str = "https://megacloud.blog";
 const MEGACLOUD_KEY_URL: &str = "https://raw.githubusercontent.com/itzzzme/megacloud-keys/refs/heads/main/key.txt";
-const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/121.0.0.0 Safari/537.36";
+/// Servers resolved at once by `stream_candidates`
+const CANDIDATE_CONCURRENCY: usize = 3;
 
 // ============================================================
 // SEARCH
@@ -16,11 +28,14 @@ const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/
 pub struct HiAnimeSearchItem {
     pub id: String,
     pub name: String,
+    /// Romaji title from the `data-jname` attribute
+    #[serde(default)]
+    pub jname: Option<String>,
     pub poster: String,
     pub duration: Option<String>,
     #[serde(rename = "type")]
-    pub anime_type: Option<String>,
-    pub rating: Option<String>,
+    pub anime_type: Option<String>, // "TV", "Movie", "OVA", ...
+    pub rating: Option<String>,     // age rating badge, e.g. "18+"
     pub episodes: Option<HiAnimeSearchEpisodes>,
 }
 
@@ -30,20 +45,167 @@ pub struct HiAnimeSearchEpisodes {
     pub dub: Option<i32>,
 }
 
-pub async fn search(query: &str) -> Result<Vec<HiAnimeSearchItem>, String> {
-    let client = Client::new();
-    let url = format!("{}/search?keyword={}", HIANIME_BASE, urlencoding::encode(query));
-    println!("DEBUG: HiAnime Direct Search: {}", url);
+/// One results page plus what the page navigation says about the rest
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct HiAnimeSearchPage {
+    pub items: Vec<HiAnimeSearchItem>,
+    pub page: u32,
+    pub has_next_page: bool,
+    pub total_pages: Option<u32>,
+}
+
+/// Catalog filters, shaped like the frontend `CatalogFilters` (AniList-style values:
+/// "TV", "RELEASING", "WINTER", "SCORE_DESC"...). Values HiAnime has no equivalent
+/// for are ignored.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HiAnimeFilters {
+    pub search: Option<String>,
+    pub year: Option<i32>,
+    pub season: Option<String>,
+    pub genres: Vec<String>,
+    pub format: Option<String>,
+    pub status: Option<String>,
+    pub sort: Option<String>,
+    /// Only titles with this audio
+    pub language: Option<TranslationType>,
+}
+
+/// Pages the plain title search follows before stopping
+const SEARCH_MAX_PAGES: u32 = 2;
+
+impl HiAnimeFilters {
+    /// Query string for `/search` (with a keyword) or `/filter` (browsing)
+    fn query_params(&self, page: u32) -> Vec<(&'static str, String)> {
+        let mut params = Vec::new();
+        if let Some(keyword) = self.keyword() {
+            params.push(("keyword", keyword.to_string()));
+        }
+
+        let format = self.format.as_deref().and_then(|f| match f {
+            "MOVIE" => Some("1"),
+            "TV" => Some("2"),
+            "OVA" => Some("3"),
+            "ONA" => Some("4"),
+            "SPECIAL" => Some("5"),
+            "MUSIC" => Some("6"),
+            _ => None,
+        });
+        let status = self.status.as_deref().and_then(|s| match s {
+            "FINISHED" => Some("1"),
+            "RELEASING" => Some("2"),
+            "NOT_YET_RELEASED" => Some("3"),
+            _ => None,
+        });
+        let season = self.season.as_deref().and_then(|s| match s {
+            "SPRING" => Some("1"),
+            "SUMMER" => Some("2"),
+            "FALL" => Some("3"),
+            "WINTER" => Some("4"),
+            _ => None,
+        });
+        let language = self.language.and_then(|l| match l {
+            TranslationType::Sub => Some("1"),
+            TranslationType::Dub => Some("2"),
+            TranslationType::Raw => None,
+        });
+        let sort = self.sort.as_deref().and_then(|s| match s {
+            "TRENDING_DESC" | "POPULARITY_DESC" => Some("most_watched"),
+            "SCORE_DESC" => Some("score"),
+            "START_DATE_DESC" => Some("released_date"),
+            "TITLE_ROMAJI" => Some("name_az"),
+            _ => None,
+        });
+
+        for (name, value) in [("type", format), ("status", status), ("season", season), ("language", language), ("sort", sort)] {
+            if let Some(value) = value {
+                params.push((name, value.to_string()));
+            }
+        }
+        if let Some(year) = self.year {
+            params.push(("sy", year.to_string()));
+        }
+
+        let genres: Vec<&str> = self.genres.iter().filter_map(|g| genre_id(g)).collect();
+        if !genres.is_empty() {
+            params.push(("genres", genres.join(",")));
+        }
+
+        if page > 1 {
+            params.push(("page", page.to_string()));
+        }
+        params
+    }
+
+    fn keyword(&self) -> Option<&str> {
+        self.search.as_deref().map(str::trim).filter(|k| !k.is_empty())
+    }
+}
+
+/// HiAnime's numeric genre IDs for the names the catalog filter offers
+fn genre_id(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "Action" => "1",
+        "Adventure" => "2",
+        "Comedy" => "4",
+        "Mystery" => "7",
+        "Drama" => "8",
+        "Ecchi" => "9",
+        "Fantasy" => "10",
+        "Horror" => "14",
+        "Mahou Shoujo" | "Magic" => "16",
+        "Mecha" => "18",
+        "Music" => "19",
+        "Romance" => "22",
+        "Sci-Fi" => "24",
+        "Sports" => "30",
+        "Slice of Life" => "36",
+        "Supernatural" => "37",
+        "Psychological" => "40",
+        "Thriller" => "41",
+        _ => return None,
+    })
+}
+
+/// Title search, following page navigation for up to `SEARCH_MAX_PAGES` pages
+pub async fn search(query: &str) -> Result<Vec<HiAnimeSearchItem>> {
+    let filters = HiAnimeFilters { search: Some(query.to_string()), ..Default::default() };
+    let mut items = Vec::new();
+
+    for page in 1..=SEARCH_MAX_PAGES {
+        let result = search_page(&filters, page).await?;
+        items.extend(result.items);
+        if !result.has_next_page {
+            break;
+        }
+    }
+
+    println!("DEBUG: HiAnime Direct Search found {} results", items.len());
+    Ok(items)
+}
+
+/// One page of a filtered search (`/search` with a keyword, `/filter` without)
+pub async fn search_page(filters: &HiAnimeFilters, page: u32) -> Result<HiAnimeSearchPage> {
+    let client = http::client();
+    let page = page.max(1);
+    let path = if filters.keyword().is_some() { "search" } else { "filter" };
+    let url = format!("{}/{}", HIANIME_BASE, path);
+    let params = filters.query_params(page);
+    println!("DEBUG: HiAnime Direct Search: {} {:?}", url, params);
 
     let res = client.get(&url)
-        .header("User-Agent", USER_AGENT)
+        .query(&params)
         .header("Referer", HIANIME_BASE)
-        .send()
-        .await
-        .map_err(|e| format!("HiAnime search request failed: {}", e))?;
+        .send_with_retry(PROVIDER)
+        .await?;
+    let res = NezukoError::check_status(PROVIDER, res)?;
+
+    let html_text = res.text().await.map_err(|e| NezukoError::from_reqwest(PROVIDER, e))?;
+    Ok(parse_search_page(&html_text, page))
+}
 
-    let html_text = res.text().await.map_err(|e| e.to_string())?;
-    let document = Html::parse_document(&html_text);
+fn parse_search_page(html: &str, page: u32) -> HiAnimeSearchPage {
+    let document = Html::parse_document(html);
 
     let item_selector = Selector::parse(".flw-item").unwrap();
     let name_selector = Selector::parse(".dynamic-name").unwrap();
@@ -52,13 +214,22 @@ pub async fn search(query: &str) -> Result<Vec<HiAnimeSearchItem>, String> {
     let sub_selector = Selector::parse(".tick-sub").unwrap();
     let dub_selector = Selector::parse(".tick-dub").unwrap();
     let duration_selector = Selector::parse(".fdi-duration").unwrap();
+    let type_selector = Selector::parse(".fd-infor .fdi-item:not(.fdi-duration)").unwrap();
+    let rating_selector = Selector::parse(".tick-rate").unwrap();
+
+    let text_of = |el: scraper::ElementRef| el.text().collect::<String>().trim().to_string();
 
-    let mut results = Vec::new();
+    let mut items = Vec::new();
 
     for element in document.select(&item_selector) {
-        let name = element.select(&name_selector).next()
+        let name_el = element.select(&name_selector).next();
+        let name = name_el
             .map(|el| el.text().collect::<Vec<_>>().join(""))
             .unwrap_or_default();
+        let jname = name_el
+            .and_then(|el| el.value().attr("data-jname"))
+            .map(|j| j.trim().to_string())
+            .filter(|j| !j.is_empty());
 
         let poster = element.select(&img_selector).next()
             .and_then(|el| el.value().attr("data-src").or(el.value().attr("src")))
@@ -75,17 +246,19 @@ pub async fn search(query: &str) -> Result<Vec<HiAnimeSearchItem>, String> {
         let dub_count = element.select(&dub_selector).next()
             .and_then(|el| el.text().collect::<String>().trim().parse::<i32>().ok());
 
-        let duration = element.select(&duration_selector).next()
-            .map(|el| el.text().collect::<String>().trim().to_string());
+        let duration = element.select(&duration_selector).next().map(text_of);
+        let anime_type = element.select(&type_selector).next().map(text_of).filter(|t| !t.is_empty());
+        let rating = element.select(&rating_selector).next().map(text_of).filter(|r| !r.is_empty());
 
         if !id.is_empty() && !name.is_empty() {
-            results.push(HiAnimeSearchItem {
+            items.push(HiAnimeSearchItem {
                 id,
                 name,
+                jname,
                 poster,
                 duration,
-                anime_type: None,
-                rating: None,
+                anime_type,
+                rating,
                 episodes: Some(HiAnimeSearchEpisodes {
                     sub: sub_count,
                     dub: dub_count,
@@ -94,8 +267,86 @@ pub async fn search(query: &str) -> Result<Vec<HiAnimeSearchItem>, String> {
         }
     }
 
-    println!("DEBUG: HiAnime Direct Search found {} results", results.len());
-    Ok(results)
+    // Page navigation: "Next" only exists when there is one; "Last" links to the final page
+    let next_selector = Selector::parse(".pagination a[title=\"Next\"]").unwrap();
+    let last_selector = Selector::parse(".pagination a[title=\"Last\"]").unwrap();
+    let has_next_page = document.select(&next_selector).next().is_some();
+    let total_pages = document.select(&last_selector).next()
+        .and_then(|el| el.value().attr("href"))
+        .and_then(page_param)
+        .or(if has_next_page { None } else { Some(page) });
+
+    HiAnimeSearchPage { items, page, has_next_page, total_pages }
+}
+
+/// `page=N` from a pagination href like "/search?keyword=naruto&page=5"
+fn page_param(href: &str) -> Option<u32> {
+    href.split(['?', '&'])
+        .find_map(|pair| pair.strip_prefix("page="))
+        .and_then(|n| n.parse().ok())
+}
+
+/// One page of a filtered catalog search as `AnimeResult`s
+pub async fn search_results_page(filters: &HiAnimeFilters, page: u32) -> Result<SearchPage> {
+    let result = search_page(filters, page).await?;
+    Ok(SearchPage {
+        results: result.items.into_iter().map(item_to_result).collect(),
+        page: result.page,
+        has_next_page: result.has_next_page,
+        total_pages: result.total_pages,
+    })
+}
+
+/// Map a search card to the shared search result shape
+fn item_to_result(item: HiAnimeSearchItem) -> AnimeResult {
+    let mut languages = Vec::new();
+    // API returns episode counts for sub/dub usually
+    if let Some(eps) = &item.episodes {
+        if eps.sub.unwrap_or(0) > 0 { languages.push("Japanese".to_string()); }
+        if eps.dub.unwrap_or(0) > 0 { languages.push("English".to_string()); }
+    } else {
+        // Default assumption if missing (safe fallback)
+        languages.push("Japanese".to_string());
+    }
+
+    let is_multi = languages.contains(&"English".to_string()) && languages.contains(&"Japanese".to_string());
+    let ep_count = item.episodes.as_ref().and_then(|e| e.sub).unwrap_or(0);
+
+    // Same show page serves both audio modes; the category in the ID picks the servers, and
+    // the dub ID remembers how far the dub goes
+    let dub_count = item.episodes.as_ref().and_then(|e| e.dub).and_then(|n| u32::try_from(n).ok());
+    let id_for = |category| MediaId::HiAnime {
+        slug: item.id.clone(),
+        category,
+        dub_episodes: if category == TranslationType::Dub { dub_count } else { None },
+    };
+    let variant_ids: HashMap<String, MediaId> = languages.iter()
+        .map(|l| if l == "English" { TranslationType::Dub } else { TranslationType::Sub })
+        .map(|category| (category.as_str().to_string(), id_for(category)))
+        .collect();
+    let id = variant_ids.get("sub").or(variant_ids.get("dub")).cloned().unwrap_or_else(|| id_for(TranslationType::Sub));
+
+    let alt_titles = item.jname.into_iter().filter(|j| *j != item.name).collect();
+
+    AnimeResult {
+        id,
+        title: item.name,
+        url: format!("https://hianime.to/{}", item.id),
+        image: item.poster,
+        release_date: None, // Could parse from 'type' or other fields if needed
+        language: Some(if is_multi { "Multi".to_string() } else { "Japanese".to_string() }),
+        is_multi_audio: is_multi,
+        available_languages: languages,
+        provider: "hianime".to_string(),
+        episode_count: Some(ep_count),
+        variant_ids,
+        alt_titles,
+        matches: Vec::new(),
+        match_confidence: None,
+        relevance: None,
+        format: item.anime_type,
+        rating: item.rating,
+    }
 }
 
 // ============================================================
@@ -112,33 +363,35 @@ pub struct HiAnimeEpisode {
     pub is_filler: bool,
 }
 
-pub async fn get_episodes(anime_id: &str) -> Result<Vec<HiAnimeEpisode>, String> {
-    let client = Client::new();
+pub async fn get_episodes(anime_id: &str) -> Result<Vec<HiAnimeEpisode>> {
+    let client = http::client();
 
     // Extract numeric ID from the slug (e.g., "one-piece-100" -> "100")
     let numeric_id = anime_id
         .rsplit('-')
         .next()
         .and_then(|s| s.parse::<i64>().ok())
-        .ok_or_else(|| format!("Cannot extract numeric ID from '{}'", anime_id))?;
+        .ok_or_else(|| NezukoError::unsupported(PROVIDER, format!("Cannot extract numeric ID from '{}'", anime_id)))?;
 
     let url = format!("{}/ajax/v2/episode/list/{}", HIANIME_BASE, numeric_id);
     println!("DEBUG: HiAnime Direct Episodes: {}", url);
 
     let res = client.get(&url)
-        .header("User-Agent", USER_AGENT)
         .header("Referer", format!("{}/{}", HIANIME_BASE, anime_id))
         .header("X-Requested-With", "XMLHttpRequest")
-        .send()
-        .await
-        .map_err(|e| format!("HiAnime episodes request failed: {}", e))?;
+        .send_with_retry(PROVIDER)
+        .await?;
+    if res.status() == 404 {
+        return Err(NezukoError::not_found(PROVIDER, format!("anime '{}'", anime_id)));
+    }
+    let res = NezukoError::check_status(PROVIDER, res)?;
 
     let json: serde_json::Value = res.json().await
-        .map_err(|e| format!("HiAnime episodes JSON parse failed: {}", e))?;
+        .map_err(|e| NezukoError::parse(PROVIDER, format!("episodes JSON: {}", e)))?;
 
     let html_str = json.get("html")
         .and_then(|v| v.as_str())
-        .ok_or("No 'html' field in episode response")?;
+        .ok_or_else(|| NezukoError::parse(PROVIDER, "No 'html' field in episode response"))?;
 
     let document = Html::parse_document(html_str);
     let ep_selector = Selector::parse(".ep-item").unwrap();
@@ -179,6 +432,7 @@ pub async fn get_episodes(anime_id: &str) -> Result<Vec<HiAnimeEpisode>, String>
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct HiAnimeSourcesData {
+    pub server: Option<String>,
     pub sources: Vec<HiAnimeSource>,
     pub tracks: Option<Vec<HiAnimeTrack>>,
     pub intro: Option<SkipTime>,
@@ -207,83 +461,104 @@ pub struct SkipTime {
     pub end: Option<f64>,
 }
 
-/// Server item from the servers response
-#[derive(Debug)]
-struct ServerItem {
-    id: String,
-    name: String,
-    server_type: String, // "sub" or "dub"
+/// One playback server for an episode, as listed by the servers endpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HiAnimeServer {
+    pub id: String,
+    pub name: String, // "HD-1", "HD-2", ...
+    #[serde(rename = "type")]
+    pub server_type: String, // "sub", "dub" or "raw"
+}
+
+/// Best server for the category: HD-1, then HD-2, then any. Only sub may fall back to
+/// another category's server, so a dub request never silently plays the sub.
+fn pick_server<'a>(servers: &'a [HiAnimeServer], category: &str) -> Option<&'a HiAnimeServer> {
+    let of_category = || servers.iter().filter(move |s| s.server_type == category);
+    of_category().find(|s| s.name.contains("HD-1") || s.name.contains("Vidstreaming"))
+        .or_else(|| of_category().find(|s| s.name.contains("HD-2") || s.name.contains("MegaCloud")))
+        .or_else(|| of_category().next())
+        .or_else(|| servers.first().filter(|_| category == "sub"))
 }
 
-pub async fn get_sources(episode_id: &str, category: &str) -> Result<HiAnimeSourcesData, String> {
-    let client = Client::new();
+/// Every server HiAnime offers for the episode, across all categories
+pub async fn get_servers(episode_id: &str) -> Result<Vec<HiAnimeServer>> {
+    let client = http::client();
 
-    // Step 1: Get servers for this episode
     let servers_url = format!("{}/ajax/v2/episode/servers?episodeId={}", HIANIME_BASE, episode_id);
     println!("DEBUG: MegaCloud Step 1 - Servers: {}", servers_url);
 
     let servers_res = client.get(&servers_url)
-        .header("User-Agent", USER_AGENT)
         .header("Referer", HIANIME_BASE)
         .header("X-Requested-With", "XMLHttpRequest")
-        .send()
-        .await
-        .map_err(|e| format!("Servers request failed: {}", e))?;
+        .send_with_retry(PROVIDER)
+        .await?;
+    let servers_res = NezukoError::check_status(PROVIDER, servers_res)?;
 
     let servers_json: serde_json::Value = servers_res.json().await
-        .map_err(|e| format!("Servers JSON parse failed: {}", e))?;
-
-    // Parse servers HTML in a block so Html (non-Send) is dropped before next .await
-    let (selected_server_id, selected_server_name) = {
-        let servers_html = servers_json.get("html")
-            .and_then(|v| v.as_str())
-            .ok_or("No 'html' in servers response")?;
-
-        let doc = Html::parse_document(servers_html);
-        let server_selector = Selector::parse(".server-item[data-id]").unwrap();
-
-        let mut servers: Vec<ServerItem> = Vec::new();
-        for el in doc.select(&server_selector) {
-            let id = el.value().attr("data-id").unwrap_or("").to_string();
-            let stype = el.value().attr("data-type").unwrap_or("sub").to_string();
-            let name = el.text().collect::<String>().trim().to_string();
-            if !id.is_empty() {
-                servers.push(ServerItem { id, name, server_type: stype });
-            }
-        }
+        .map_err(|e| NezukoError::parse(PROVIDER, format!("servers JSON: {}", e)))?;
+
+    let servers_html = servers_json.get("html")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| NezukoError::parse(PROVIDER, "No 'html' in servers response"))?;
+
+    let servers = parse_servers(servers_html);
+    println!("DEBUG: Found {} servers: {:?}", servers.len(), servers.iter().map(|s| format!("{}({}/{})", s.name, s.server_type, s.id)).collect::<Vec<_>>());
+    Ok(servers)
+}
 
-        println!("DEBUG: Found {} servers: {:?}", servers.len(), servers.iter().map(|s| format!("{}({}/{})", s.name, s.server_type, s.id)).collect::<Vec<_>>());
+fn parse_servers(html: &str) -> Vec<HiAnimeServer> {
+    let doc = Html::parse_document(html);
+    let server_selector = Selector::parse(".server-item[data-id]").unwrap();
+
+    let mut servers = Vec::new();
+    for el in doc.select(&server_selector) {
+        let id = el.value().attr("data-id").unwrap_or("").to_string();
+        let server_type = el.value().attr("data-type").unwrap_or("sub").to_string();
+        let name = el.text().collect::<String>().trim().to_string();
+        if !id.is_empty() {
+            servers.push(HiAnimeServer { id, name, server_type });
+        }
+    }
+    servers
+}
 
-        // Pick best server: prefer HD-1/HD-2 matching the requested category (sub/dub)
-        let target = servers.iter()
-            .find(|s| s.server_type == category && (s.name.contains("HD-1") || s.name.contains("Vidstreaming")))
-            .or_else(|| servers.iter().find(|s| s.server_type == category && (s.name.contains("HD-2") || s.name.contains("MegaCloud"))))
-            .or_else(|| servers.iter().find(|s| s.server_type == category))
-            .or_else(|| servers.first())
-            .ok_or("No servers found for this episode")?;
+/// Sources for an episode from `server` (an ID from `get_servers`), or from the
+/// best server of `category` when no server is given
+pub async fn get_sources(episode_id: &str, category: &str, server: Option<&str>) -> Result<HiAnimeSourcesData> {
+    // Step 1: Get servers for this episode
+    let servers = get_servers(episode_id).await?;
+    let target = match server {
+        Some(wanted) => servers.iter().find(|s| s.id == wanted)
+            .ok_or_else(|| NezukoError::not_found(PROVIDER, format!("Server {} is not listed for episode {}", wanted, episode_id)))?,
+        None => pick_server(&servers, category)
+            .ok_or_else(|| NezukoError::not_found(PROVIDER, format!("No {} servers found for episode {}", category, episode_id)))?,
+    };
+    server_sources(target.clone()).await
+}
 
-        println!("DEBUG: Selected server: {} (type={}, id={})", target.name, target.server_type, target.id);
-        (target.id.clone(), target.name.clone())
-    }; // doc is dropped here — safe to .await below
+/// Sources behind one server from `get_servers`
+async fn server_sources(target: HiAnimeServer) -> Result<HiAnimeSourcesData> {
+    let client = http::client();
+    println!("DEBUG: Selected server: {} (type={}, id={})", target.name, target.server_type, target.id);
+    let (selected_server_id, selected_server_name) = (target.id.clone(), target.name.clone());
 
     // Step 2: Get source link from selected server
     let sources_url = format!("{}/ajax/v2/episode/sources?id={}", HIANIME_BASE, selected_server_id);
     println!("DEBUG: MegaCloud Step 2 - Sources: {}", sources_url);
 
     let sources_res = client.get(&sources_url)
-        .header("User-Agent", USER_AGENT)
         .header("Referer", HIANIME_BASE)
         .header("X-Requested-With", "XMLHttpRequest")
-        .send()
-        .await
-        .map_err(|e| format!("Sources request failed: {}", e))?;
+        .send_with_retry(PROVIDER)
+        .await?;
+    let sources_res = NezukoError::check_status(PROVIDER, sources_res)?;
 
     let sources_json: serde_json::Value = sources_res.json().await
-        .map_err(|e| format!("Sources JSON parse failed: {}", e))?;
+        .map_err(|e| NezukoError::parse(PROVIDER, format!("sources JSON: {}", e)))?;
 
     let embed_link = sources_json.get("link")
         .and_then(|v| v.as_str())
-        .ok_or("No 'link' in sources response")?;
+        .ok_or_else(|| NezukoError::parse(PROVIDER, "No 'link' in sources response"))?;
 
     println!("DEBUG: MegaCloud embed link: {}", embed_link);
 
@@ -298,25 +573,25 @@ pub async fn get_sources(episode_id: &str, category: &str) -> Result<HiAnimeSour
         .unwrap_or("");
 
     if server_id.is_empty() {
-        return Err("Could not extract server ID from embed link".to_string());
+        return Err(NezukoError::parse(PROVIDER, "Could not extract server ID from embed link"));
     }
 
     let get_sources_url = format!("{}/embed-2/v2/e-1/getSources?id={}", MEGACLOUD_BASE, server_id);
     println!("DEBUG: MegaCloud Step 3 - getSources: {}", get_sources_url);
 
     let mega_res = client.get(&get_sources_url)
-        .header("User-Agent", USER_AGENT)
         .header("Referer", format!("{}/", MEGACLOUD_BASE))
         .header("X-Requested-With", "XMLHttpRequest")
-        .send()
-        .await
-        .map_err(|e| format!("MegaCloud getSources request failed: {}", e))?;
+        .send_with_retry(PROVIDER)
+        .await?;
+    let mega_res = NezukoError::check_status(PROVIDER, mega_res)?;
 
     let mega_json: serde_json::Value = mega_res.json().await
-        .map_err(|e| format!("MegaCloud getSources JSON parse failed: {}", e))?;
+        .map_err(|e| NezukoError::parse(PROVIDER, format!("MegaCloud getSources JSON: {}", e)))?;
 
     // Check if sources are encrypted (string) or plain (array)
-    let sources = mega_json.get("sources").ok_or("No 'sources' in MegaCloud response")?;
+    let sources = mega_json.get("sources")
+        .ok_or_else(|| NezukoError::parse(PROVIDER, "No 'sources' in MegaCloud response"))?;
 
     let source_list: Vec<HiAnimeSource> = if sources.is_string() {
         // Encrypted — need to decrypt with AES key
@@ -325,12 +600,11 @@ pub async fn get_sources(episode_id: &str, category: &str) -> Result<HiAnimeSour
 
         // Step 4: Fetch dynamic AES key from GitHub
         let key = client.get(MEGACLOUD_KEY_URL)
-            .send()
-            .await
-            .map_err(|e| format!("Key fetch failed: {}", e))?
+            .send_with_retry(PROVIDER)
+            .await?
             .text()
             .await
-            .map_err(|e| format!("Key read failed: {}", e))?
+            .map_err(|e| NezukoError::from_reqwest(PROVIDER, e))?
             .trim()
             .to_string();
 
@@ -338,13 +612,13 @@ pub async fn get_sources(episode_id: &str, category: &str) -> Result<HiAnimeSour
 
         // Step 5: Decrypt using gogaes (OpenSSL Salted__ AES-256-CBC)
         let decrypted = gogaes::decrypt_aes_crypto_js(encrypted_b64, &key)
-            .map_err(|e| format!("MegaCloud AES decrypt failed: {}", e))?;
+            .map_err(|e| NezukoError::decrypt(PROVIDER, format!("MegaCloud AES: {}", e)))?;
 
         println!("DEBUG: MegaCloud decrypted: {}...", &decrypted[..std::cmp::min(200, decrypted.len())]);
 
         // Parse the decrypted JSON — it's an array of {file, type}
         let parsed: serde_json::Value = serde_json::from_str(&decrypted)
-            .map_err(|e| format!("Decrypted JSON parse failed: {}", e))?;
+            .map_err(|e| NezukoError::decrypt(PROVIDER, format!("decrypted payload is not JSON: {}", e)))?;
 
         if let Some(arr) = parsed.as_array() {
             arr.iter().filter_map(|item| {
@@ -357,7 +631,7 @@ pub async fn get_sources(episode_id: &str, category: &str) -> Result<HiAnimeSour
                 })
             }).collect()
         } else {
-            return Err("Decrypted sources is not an array".to_string());
+            return Err(NezukoError::parse(PROVIDER, "Decrypted sources is not an array"));
         }
     } else if sources.is_array() {
         // Already plain JSON array
@@ -371,7 +645,7 @@ pub async fn get_sources(episode_id: &str, category: &str) -> Result<HiAnimeSour
             })
         }).collect()
     } else {
-        return Err("Unexpected 'sources' format in MegaCloud response".to_string());
+        return Err(NezukoError::parse(PROVIDER, "Unexpected 'sources' format in MegaCloud response"));
     };
 
     // Extract tracks (subtitles)
@@ -411,9 +685,429 @@ pub async fn get_sources(episode_id: &str, category: &str) -> Result<HiAnimeSour
     );
 
     Ok(HiAnimeSourcesData {
+        server: Some(selected_server_name),
         sources: source_list,
         tracks,
         intro,
         outro,
     })
 }
+
+/// Every source from one server (`None` = best server for the episode's category)
+pub async fn stream_from_server(episode_id: &EpisodeId, server: Option<&str>) -> Result<Vec<VideoSource>> {
+    let EpisodeId::HiAnime { episode_id: ep_id, category, .. } = episode_id else {
+        return Err(NezukoError::unsupported(PROVIDER, format!("Not a HiAnime episode ID: {}", episode_id)));
+    };
+
+    println!("DEBUG: HiAnime Direct stream: ep_id={}, category={}", ep_id, category);
+
+    let data = get_sources(ep_id, category.as_str(), server).await?;
+    Ok(to_video_sources(data))
+}
+
+fn to_video_sources(data: HiAnimeSourcesData) -> Vec<VideoSource> {
+    let server = data.server.clone().unwrap_or_else(|| "MegaCloud".to_string());
+    let segments = skip_segments(&data);
+
+    // Map subtitle tracks (skip thumbnails)
+    let mut tracks = Vec::new();
+    if let Some(ts) = data.tracks {
+        for t in ts {
+            if t.kind == "captions" {
+                tracks.push(SubtitleTrack {
+                    label: t.label.unwrap_or("English".to_string()),
+                    file: t.file,
+                    kind: "captions".to_string(),
+                });
+            }
+        }
+    }
+
+    tracks.extend(chapters::chapters_track(&segments));
+
+    data.sources.iter().map(|src| {
+        // Proxy the M3U8 with MegaCloud referer for CORS
+        let is_hls = src.source_type == "hls" || src.url.contains(".m3u8");
+        let final_url = if is_hls {
+            let headers = serde_json::json!({
+                "Referer": "https://megacloud.blog/",
+                "Origin": "https://megacloud.blog/"
+            }).to_string();
+            proxy::proxy_url(&src.url, &headers)
+        } else {
+            src.url.clone()
+        };
+
+        println!("DEBUG: HiAnime Direct stream SUCCESS: {} (hls={})", &src.url[..std::cmp::min(80, src.url.len())], is_hls);
+
+        VideoSource {
+            url: final_url,
+            quality: src.quality.clone().unwrap_or_else(|| "auto".to_string()),
+            is_m3u8: is_hls,
+            subtitles: tracks.clone(),
+            audio_tracks: Vec::new(),
+            provider: "Gold".to_string(),
+            provider_id: "hianime".to_string(),
+            server: server.clone(),
+            source_type: if is_hls { "hls" } else { "mp4" }.to_string(),
+            status: StreamStatus::Resolved,
+            error: None,
+            segments: segments.clone(),
+            variants: Vec::new(),
+        }
+    }).collect()
+}
+
+/// Servers to offer as candidates: the one `pick_server` would use, the rest of the
+/// category, then the other categories. Empty when the category itself has no server.
+fn candidate_servers<'a>(servers: &'a [HiAnimeServer], category: &str) -> Vec<&'a HiAnimeServer> {
+    let Some(best) = pick_server(servers, category) else {
+        return Vec::new();
+    };
+    let mut ordered = vec![best];
+    ordered.extend(servers.iter().filter(|s| s.server_type == best.server_type && s.id != best.id));
+    ordered.extend(servers.iter().filter(|s| s.server_type != best.server_type));
+    ordered
+}
+
+/// MegaCloud's intro/outro markers; it sends 0-0 when an episode has none
+fn skip_segments(data: &HiAnimeSourcesData) -> Vec<SkipSegment> {
+    [(SegmentKind::Intro, &data.intro), (SegmentKind::Outro, &data.outro)]
+        .into_iter()
+        .filter_map(|(kind, time)| {
+            let time = time.as_ref()?;
+            SkipSegment::new(kind, time.start?, time.end?)
+        })
+        .collect()
+}
+
+fn to_episode(e: HiAnimeEpisode, slug: &str, category: TranslationType) -> Episode {
+    let mut episode = Episode::new(
+        EpisodeId::HiAnime {
+            slug: slug.to_string(),
+            category,
+            episode_id: e.episode_id.clone(),
+        },
+        e.number as f32,
+        format!("https://hianime.to/watch/{}", e.episode_id),
+    );
+    // "Episode 12" is the list's placeholder; leave the title for AniZip to fill
+    if !e.title.is_empty() && e.title != format!("Episode {}", e.number) {
+        episode.set_title(e.title);
+    }
+    episode.is_filler = e.is_filler;
+    episode
+}
+
+/// Episodes for one category, the dub cut off after its `dubbed` count
+fn episode_listing(eps: Vec<HiAnimeEpisode>, slug: &str, category: TranslationType, dubbed: Option<usize>) -> Vec<Episode> {
+    let total = eps.len();
+    let listed = dubbed.unwrap_or(total).min(total);
+    if listed < total {
+        println!("DEBUG get_episodes: HiAnime dub covers {} of {} episodes", listed, total);
+    }
+    eps.into_iter().take(listed).map(|e| to_episode(e, slug, category)).collect()
+}
+
+async fn has_dub_server(episode_id: &str) -> Result<bool> {
+    Ok(get_servers(episode_id).await?.iter().any(|s| s.server_type == "dub"))
+}
+
+/// How many leading episodes have a dub server. Dubs are released in order, so this
+/// bisects the list; if a probe fails, the whole list is assumed dubbed.
+async fn dubbed_prefix(eps: &[HiAnimeEpisode]) -> usize {
+    let Some(last) = eps.last() else { return 0 };
+    match has_dub_server(&last.episode_id).await {
+        Ok(false) => {},
+        _ => return eps.len(),
+    }
+    // eps[..low] are dubbed, eps[high] is not
+    let (mut low, mut high) = (0, eps.len() - 1);
+    while low < high {
+        let mid = (low + high) / 2;
+        match has_dub_server(&eps[mid].episode_id).await {
+            Ok(true) => low = mid + 1,
+            Ok(false) => high = mid,
+            Err(_) => return eps.len(),
+        }
+    }
+    low
+}
+
+// ============================================================
+// PROVIDER (Gold)
+// ============================================================
+
+pub struct HiAnimeProvider;
+
+#[async_trait]
+impl Provider for HiAnimeProvider {
+    fn info(&self) -> ProviderInfo {
+        ProviderInfo {
+            id: "hianime",
+            name: "HiAnime",
+            tier: "Gold",
+        }
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<AnimeResult>> {
+        let items = search(query).await?;
+
+        Ok(items.into_iter().map(item_to_result).collect())
+    }
+
+    async fn episodes(&self, anime_id: &MediaId) -> Result<Vec<Episode>> {
+        let MediaId::HiAnime { slug: real_id, category, dub_episodes } = anime_id else {
+            return Err(NezukoError::unsupported(PROVIDER, format!("Not a HiAnime ID: {}", anime_id)));
+        };
+        println!("DEBUG get_episodes: HiAnime detected, real_id = '{}'", real_id);
+
+        let eps = get_episodes(real_id).await?;
+        println!("DEBUG get_episodes: HiAnime returned {} episodes", eps.len());
+
+        // The list is the sub's; a dub only reaches so far. IDs that don't say how far
+        // (AniZip mappings, older IDs) find out from the episodes' servers.
+        let dubbed = match (category, dub_episodes) {
+            (TranslationType::Dub, Some(count)) => Some(*count as usize),
+            (TranslationType::Dub, None) => Some(dubbed_prefix(&eps).await),
+            _ => None,
+        };
+        Ok(episode_listing(eps, real_id, *category, dubbed))
+    }
+
+    fn media_id_from_mappings(&self, mappings: &ProviderMappings, language: TranslationType) -> Option<MediaId> {
+        // Zoro IDs are HiAnime slugs; the same page has the dub servers when there is a dub
+        match language {
+            TranslationType::Sub | TranslationType::Dub => Some(MediaId::HiAnime { slug: mappings.zoro_id.clone()?, category: language, dub_episodes: None }),
+            TranslationType::Raw => None,
+        }
+    }
+
+    async fn stream(&self, episode_id: &EpisodeId) -> Result<VideoSource> {
+        stream_from_server(episode_id, None).await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| NezukoError::not_found(PROVIDER, "No sources in HiAnime response"))
+    }
+
+    /// Sources from every server of the episode, so the player can switch between them.
+    /// Servers of another category are labelled with it, e.g. "HD-1 (sub)".
+    async fn stream_candidates(&self, episode_id: &EpisodeId) -> Result<Vec<VideoSource>> {
+        let EpisodeId::HiAnime { episode_id: ep_id, category, .. } = episode_id else {
+            return Err(NezukoError::unsupported(PROVIDER, format!("Not a HiAnime episode ID: {}", episode_id)));
+        };
+        let servers = get_servers(ep_id).await?;
+        let ordered = candidate_servers(&servers, category.as_str());
+        if ordered.is_empty() {
+            return Err(NezukoError::not_found(PROVIDER, format!("No {} servers found for episode {}", category.as_str(), ep_id)));
+        }
+
+        let resolved: Vec<Result<HiAnimeSourcesData>> = stream::iter(ordered.iter().map(|s| (*s).clone()).collect::<Vec<_>>())
+            .map(server_sources)
+            .buffered(CANDIDATE_CONCURRENCY)
+            .collect()
+            .await;
+
+        // Keep server order, but playable sources first
+        let (mut ok, failed): (Vec<_>, Vec<_>) = ordered.iter().zip(resolved)
+            .flat_map(|(server, res)| {
+                let label = if server.server_type == category.as_str() {
+                    server.name.clone()
+                } else {
+                    format!("{} ({})", server.name, server.server_type)
+                };
+                match res {
+                    Ok(data) => to_video_sources(data).into_iter()
+                        .map(|mut v| { v.server = label.clone(); v })
+                        .collect::<Vec<_>>(),
+                    Err(e) => vec![VideoSource::failed(&label, "Gold", PROVIDER, &e)],
+                }
+            })
+            .partition(|v| v.status == StreamStatus::Resolved);
+        ok.extend(failed);
+
+        println!("DEBUG: HiAnime candidates: {:?}", ok.iter().map(|v| (&v.server, v.status)).collect::<Vec<_>>());
+        Ok(ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEARCH_HTML: &str = r#"
+        <div class="film_list-wrap">
+          <div class="flw-item">
+            <div class="film-poster">
+              <div class="tick tick-rate">18+</div>
+              <div class="tick ltr"><div class="tick-item tick-sub">28</div><div class="tick-item tick-dub">28</div></div>
+              <img data-src="https://img.example/frieren.jpg" class="film-poster-img">
+              <a href="/watch/frieren-beyond-journeys-end-18542" class="film-poster-ahref"></a>
+            </div>
+            <div class="film-detail">
+              <h3 class="film-name"><a href="/frieren-beyond-journeys-end-18542" class="dynamic-name" data-jname="Sousou no Frieren">Frieren: Beyond Journey's End</a></h3>
+              <div class="fd-infor"><span class="fdi-item">TV</span><span class="dot"></span><span class="fdi-item fdi-duration">24m</span></div>
+            </div>
+          </div>
+          <div class="flw-item">
+            <div class="film-poster">
+              <img src="https://img.example/special.jpg">
+              <a href="/watch/frieren-mini-anime-19017"></a>
+            </div>
+            <div class="film-detail">
+              <h3 class="film-name"><a class="dynamic-name" data-jname="">Frieren Mini Anime</a></h3>
+              <div class="fd-infor"><span class="fdi-item">ONA</span></div>
+            </div>
+          </div>
+        </div>
+        <ul class="pagination">
+          <li class="page-item active"><a class="page-link">2</a></li>
+          <li class="page-item"><a title="Page 3" class="page-link" href="/search?keyword=frieren&page=3">3</a></li>
+          <li class="page-item"><a title="Next" class="page-link" href="/search?keyword=frieren&page=3">&rsaquo;</a></li>
+          <li class="page-item"><a title="Last" class="page-link" href="/search?keyword=frieren&page=4">&raquo;</a></li>
+        </ul>
+    "#;
+
+    #[test]
+    fn test_parse_search_cards_and_pagination() {
+        let page = parse_search_page(SEARCH_HTML, 2);
+        assert_eq!(page.items.len(), 2);
+        assert!(page.has_next_page);
+        assert_eq!(page.total_pages, Some(4));
+
+        let frieren = &page.items[0];
+        assert_eq!(frieren.id, "frieren-beyond-journeys-end-18542");
+        assert_eq!(frieren.jname.as_deref(), Some("Sousou no Frieren"));
+        assert_eq!(frieren.anime_type.as_deref(), Some("TV"));
+        assert_eq!(frieren.rating.as_deref(), Some("18+"));
+        assert_eq!(frieren.duration.as_deref(), Some("24m"));
+        assert_eq!(frieren.episodes, Some(HiAnimeSearchEpisodes { sub: Some(28), dub: Some(28) }));
+
+        let mini = &page.items[1];
+        assert_eq!(mini.jname, None);
+        assert_eq!(mini.anime_type.as_deref(), Some("ONA"));
+        assert_eq!(mini.rating, None);
+
+        let last = parse_search_page("<div class=\"flw-item\"></div>", 4);
+        assert!(!last.has_next_page);
+        assert_eq!(last.total_pages, Some(4));
+    }
+
+    #[test]
+    fn test_filters_map_to_query_params() {
+        let filters: HiAnimeFilters = serde_json::from_value(serde_json::json!({
+            "year": 2023,
+            "season": "FALL",
+            "genres": ["Adventure", "Fantasy", "Not A Genre"],
+            "format": "TV",
+            "status": "FINISHED",
+            "sort": "SCORE_DESC",
+            "language": "dub",
+        })).unwrap();
+
+        assert_eq!(filters.query_params(3), vec![
+            ("type", "2".to_string()),
+            ("status", "1".to_string()),
+            ("season", "3".to_string()),
+            ("language", "2".to_string()),
+            ("sort", "score".to_string()),
+            ("sy", "2023".to_string()),
+            ("genres", "2,10".to_string()),
+            ("page", "3".to_string()),
+        ]);
+
+        let search = HiAnimeFilters { search: Some(" naruto ".to_string()), ..Default::default() };
+        assert_eq!(search.query_params(1), vec![("keyword", "naruto".to_string())]);
+    }
+
+    #[test]
+    fn test_pick_server_never_swaps_dub_for_sub() {
+        let server = |id: &str, name: &str, server_type: &str| HiAnimeServer {
+            id: id.to_string(),
+            name: name.to_string(),
+            server_type: server_type.to_string(),
+        };
+        let servers = vec![
+            server("1", "HD-2", "sub"),
+            server("2", "HD-1", "sub"),
+            server("3", "HD-2", "dub"),
+            server("4", "HD-1", "raw"),
+        ];
+        assert_eq!(pick_server(&servers, "sub").unwrap().id, "2");
+        assert_eq!(pick_server(&servers, "dub").unwrap().id, "3");
+        assert_eq!(pick_server(&servers, "raw").unwrap().id, "4");
+
+        let sub_only = &servers[..2];
+        assert!(pick_server(sub_only, "dub").is_none());
+        let raw_only = &servers[3..];
+        assert_eq!(pick_server(raw_only, "sub").unwrap().id, "4");
+
+        // Candidates: picked server, rest of its category, then the others
+        let ids = |category: &str| candidate_servers(&servers, category).iter().map(|s| s.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids("sub"), vec!["2", "1", "3", "4"]);
+        assert_eq!(ids("dub"), vec!["3", "1", "2", "4"]);
+        assert!(candidate_servers(sub_only, "dub").is_empty());
+    }
+
+    #[test]
+    fn test_parse_servers_lists_every_category() {
+        let html = r#"
+            <div class="ps_-block ps_-block-sub servers-sub">
+              <div class="item server-item" data-type="sub" data-id="641233" data-server-id="4"><a class="btn">HD-1</a></div>
+              <div class="item server-item" data-type="sub" data-id="641234" data-server-id="1"><a class="btn">HD-2</a></div>
+            </div>
+            <div class="ps_-block ps_-block-sub servers-dub">
+              <div class="item server-item" data-type="dub" data-id="641240" data-server-id="4"><a class="btn">HD-1</a></div>
+            </div>
+        "#;
+        let servers = parse_servers(html);
+        assert_eq!(servers.len(), 3);
+        assert_eq!(servers[2], HiAnimeServer { id: "641240".to_string(), name: "HD-1".to_string(), server_type: "dub".to_string() });
+        assert_eq!(serde_json::to_value(&servers[0]).unwrap()["type"], "sub");
+    }
+
+    #[test]
+    fn test_search_card_gives_one_id_per_audio_category() {
+        let item = HiAnimeSearchItem {
+            id: "frieren-18542".to_string(),
+            name: "Frieren".to_string(),
+            episodes: Some(HiAnimeSearchEpisodes { sub: Some(28), dub: Some(20) }),
+            ..Default::default()
+        };
+        let result = item_to_result(item);
+        let id = |category, dub_episodes| MediaId::HiAnime { slug: "frieren-18542".to_string(), category, dub_episodes };
+        assert_eq!(result.id, id(TranslationType::Sub, None));
+        assert_eq!(result.variant_ids.get("sub"), Some(&id(TranslationType::Sub, None)));
+        assert_eq!(result.variant_ids.get("dub"), Some(&id(TranslationType::Dub, Some(20))));
+    }
+
+    #[test]
+    fn test_dub_listing_stops_at_the_dub_count() {
+        let eps = || (1..=28).map(|n| HiAnimeEpisode {
+            title: format!("Episode {}", n),
+            episode_id: (1000 + n).to_string(),
+            number: n,
+            is_filler: false,
+        }).collect::<Vec<_>>();
+
+        let dub = episode_listing(eps(), "frieren-18542", TranslationType::Dub, Some(20));
+        assert_eq!(dub.len(), 20);
+        assert_eq!(dub.last().unwrap().id.to_string(), "v1:hianime:frieren-18542:dub:1020");
+        assert_eq!(episode_listing(eps(), "frieren-18542", TranslationType::Sub, None).len(), 28);
+        assert_eq!(episode_listing(eps(), "frieren-18542", TranslationType::Dub, Some(40)).len(), 28);
+
+        let id: MediaId = "v1:hianime:frieren-18542:dub:20".parse().unwrap();
+        assert_eq!(id, MediaId::HiAnime { slug: "frieren-18542".to_string(), category: TranslationType::Dub, dub_episodes: Some(20) });
+    }
+
+    #[test]
+    fn test_skip_segments_drop_missing_markers() {
+        let data = HiAnimeSourcesData {
+            intro: Some(SkipTime { start: Some(31.0), end: Some(121.0) }),
+            outro: Some(SkipTime { start: Some(0.0), end: Some(0.0) }),
+            ..Default::default()
+        };
+        assert_eq!(skip_segments(&data), vec![SkipSegment { kind: SegmentKind::Intro, start: 31.0, end: 121.0 }]);
+        assert!(skip_segments(&HiAnimeSourcesData::default()).is_empty());
+    }
+}
@@ -1,7 +1,10 @@
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use crate::anime::Episode;
+use crate::error::{NezukoError, Result};
+use crate::http::{self, RequestExt};
 
+const PROVIDER: &str = "anizip";
 const ANIZIP_API: &str = "https://api.ani.zip";
 
 /// Provider mappings from AniZip API
@@ -15,14 +18,48 @@ pub struct ProviderMappings {
     pub zoro_id: Option<String>,
     pub episode_count: Option<i32>,
     pub titles: Option<std::collections::HashMap<String, String>>,
+    // Show titles by language ("en", "x-jat", "ja"), used for title-search fallback
+    #[serde(default)]
+    pub show_titles: std::collections::HashMap<String, String>,
     pub overviews: Option<std::collections::HashMap<String, String>>,
     pub images: Option<std::collections::HashMap<String, String>>,
+    pub air_dates: Option<std::collections::HashMap<String, String>>,
+    // Episode length in minutes
+    pub runtimes: Option<std::collections::HashMap<String, u32>>,
+}
+
+impl ProviderMappings {
+    /// Fill in episode metadata the provider didn't have, matched by episode number.
+    /// Provider titles win; AniZip supplies everything else.
+    pub fn enrich_episodes(&self, episodes: &mut [Episode]) {
+        fn lookup<T: Clone>(map: &Option<std::collections::HashMap<String, T>>, key: &str) -> Option<T> {
+            map.as_ref().and_then(|m| m.get(key)).cloned()
+        }
+
+        for episode in episodes.iter_mut() {
+            // AniZip keys regular episodes "1", "2"...; fractional ones (12.5) have no entry
+            if episode.number.fract() != 0.0 {
+                continue;
+            }
+            let key = (episode.number as i32).to_string();
+
+            if episode.title.is_none() {
+                if let Some(title) = lookup(&self.titles, &key) {
+                    episode.set_title(title);
+                }
+            }
+            episode.synopsis = episode.synopsis.take().or_else(|| lookup(&self.overviews, &key));
+            episode.thumbnail = episode.thumbnail.take().or_else(|| lookup(&self.images, &key));
+            episode.air_date = episode.air_date.take().or_else(|| lookup(&self.air_dates, &key));
+            episode.runtime = episode.runtime.or_else(|| lookup(&self.runtimes, &key));
+        }
+    }
 }
 
 /// Fetch provider mappings from AniZip API
 /// Returns mappings for AllAnime, Gogoanime, Zoro, etc.
-pub async fn get_mappings(anilist_id: i32) -> Result<ProviderMappings, String> {
-    let client = Client::new();
+pub async fn get_mappings(anilist_id: i32) -> Result<ProviderMappings> {
+    let client = http::client();
     // CORRECT URL format: query parameter, not path
     let url = format!("{}/mappings?anilist_id={}", ANIZIP_API, anilist_id);
     
@@ -31,20 +68,17 @@ pub async fn get_mappings(anilist_id: i32) -> Result<ProviderMappings, String> {
     let res = client
         .get(&url)
         .header("User-Agent", "NezukoChan/1.0")
-        .send()
-        .await
-        .map_err(|e| format!("Failed to fetch mappings: {}", e))?;
+        .send_with_retry(PROVIDER)
+        .await?;
     
     if res.status() == 404 {
         println!("[mapping] No mappings found for AniList ID: {}", anilist_id);
-        return Err("No mappings found".to_string());
+        return Err(NezukoError::not_found(PROVIDER, format!("No mappings for AniList ID {}", anilist_id)));
     }
     
-    if !res.status().is_success() {
-        return Err(format!("AniZip API error: {}", res.status()));
-    }
+    let res = NezukoError::check_status(PROVIDER, res)?;
     
-    let json: Value = res.json().await.map_err(|e| e.to_string())?;
+    let json: Value = res.json().await.map_err(|e| NezukoError::parse(PROVIDER, e))?;
     
     // Parse the AniZip response
     let mappings = parse_anizip_response(anilist_id, &json);
@@ -69,6 +103,14 @@ fn parse_anizip_response(anilist_id: i32, json: &Value) -> ProviderMappings {
         if let Some(id) = mapping_obj.get("anidb_id").and_then(|v| v.as_i64()) { mappings.anidb_id = Some(id as i32); }
     }
     
+    if let Some(titles_obj) = json.get("titles").and_then(|v| v.as_object()) {
+        for (key, val) in titles_obj {
+            if let Some(title) = val.as_str() {
+                mappings.show_titles.insert(key.clone(), title.to_string());
+            }
+        }
+    }
+
     // Parse Episodes Object (Rich Metadata)
     if let Some(episodes) = json.get("episodes").and_then(|v| v.as_object()) {
         mappings.episode_count = Some(episodes.len() as i32);
@@ -76,6 +118,8 @@ fn parse_anizip_response(anilist_id: i32, json: &Value) -> ProviderMappings {
         let mut titles_map = std::collections::HashMap::new();
         let mut overviews_map = std::collections::HashMap::new();
         let mut images_map = std::collections::HashMap::new();
+        let mut air_dates_map = std::collections::HashMap::new();
+        let mut runtimes_map = std::collections::HashMap::new();
 
         for (key, val) in episodes {
             // Title
@@ -96,11 +140,21 @@ fn parse_anizip_response(anilist_id: i32, json: &Value) -> ProviderMappings {
             if let Some(image) = val.get("image").and_then(|v| v.as_str()) {
                 images_map.insert(key.clone(), image.to_string());
             }
+
+            // Air date ("2023-09-29") and runtime (minutes)
+            if let Some(date) = val.get("airdate").or_else(|| val.get("airDate")).and_then(|v| v.as_str()) {
+                air_dates_map.insert(key.clone(), date.to_string());
+            }
+            if let Some(runtime) = val.get("runtime").or_else(|| val.get("length")).and_then(|v| v.as_u64()) {
+                runtimes_map.insert(key.clone(), runtime as u32);
+            }
         }
         
         mappings.titles = Some(titles_map);
         mappings.overviews = Some(overviews_map);
         mappings.images = Some(images_map);
+        mappings.air_dates = Some(air_dates_map);
+        mappings.runtimes = Some(runtimes_map);
     } else if let Some(titles_obj) = json.get("titles").and_then(|v| v.as_object()) {
         // Fallback to legacy "titles" object if "episodes" is missing
         let mut titles_map = std::collections::HashMap::new();
@@ -148,6 +202,38 @@ fn parse_anizip_response(anilist_id: i32, json: &Value) -> ProviderMappings {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_enrich_episodes_by_number() {
+        let json = serde_json::json!({
+            "episodes": {
+                "1": { "title": { "en": "The Journey's End" }, "overview": "The party returns.", "image": "https://img/1.jpg", "airdate": "2023-09-29", "runtime": 24 },
+                "2": { "title": { "x-jat": "Souryo no Sozai" }, "length": 24 },
+                "3": { "title": { "en": "Recap: The Story So Far" } }
+            }
+        });
+        let mappings = parse_anizip_response(154587, &json);
+
+        let id = |n: &str| crate::ids::EpisodeId::Anitaku { slug: format!("frieren-episode-{}", n) };
+        let mut episodes = vec![
+            Episode::new(id("1"), 1.0, String::new()),
+            Episode::new(id("2"), 2.0, String::new()),
+            Episode::new(id("3"), 3.0, String::new()),
+            Episode::new(id("3-5"), 3.5, String::new()),
+        ];
+        episodes[1].set_title("It Didn't Have to Be Magic".to_string());
+        mappings.enrich_episodes(&mut episodes);
+
+        assert_eq!(episodes[0].title.as_deref(), Some("The Journey's End"));
+        assert_eq!(episodes[0].synopsis.as_deref(), Some("The party returns."));
+        assert_eq!(episodes[0].thumbnail.as_deref(), Some("https://img/1.jpg"));
+        assert_eq!(episodes[0].air_date.as_deref(), Some("2023-09-29"));
+        assert_eq!(episodes[0].runtime, Some(24));
+        assert_eq!(episodes[1].title.as_deref(), Some("It Didn't Have to Be Magic"));
+        assert_eq!(episodes[1].runtime, Some(24));
+        assert!(episodes[2].is_recap && !episodes[0].is_recap);
+        assert!(episodes[3].title.is_none());
+    }
+
     #[tokio::test]
     async fn test_get_mappings_one_piece() {
         println!("Testing AniZip mappings for One Piece (AniList ID: 21)");
@@ -3,4 +3,5 @@ pub mod hianime;
 pub mod unpacker;
 
 pub mod allanime;
+pub mod anitaku;
 pub mod mapping;
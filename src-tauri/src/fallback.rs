@@ -0,0 +1,185 @@
+use serde::Serialize;
+use crate::anime::{AnimeResult, StreamStatus, VideoSource};
+use crate::error::{NezukoError, Result};
+use crate::extractors::mapping::ProviderMappings;
+use crate::ids::{EpisodeId, MediaId, TranslationType};
+use crate::merge::normalize_title;
+use crate::provider::ProviderRegistry;
+
+// ============================================================
+// Cross-provider fallback by AniList ID
+// ------------------------------------------------------------
+// Walks the registry in priority order (Diamond → Gold → Silver).
+// For each provider: mapped ID from AniZip if it has one, otherwise
+// an exact title match from a search; then the episode list, then
+// the stream. The first playable source wins.
+// ============================================================
+
+/// A stream found by the fallback walk, plus which provider served it
+#[derive(Serialize, Debug, Clone)]
+pub struct ResolvedStream {
+    #[serde(flatten)]
+    pub source: VideoSource,
+    pub served_by: String, // "allanime", "hianime", "anitaku"
+    pub media_id: MediaId,
+    pub episode_id: EpisodeId,
+    /// Why each earlier provider was skipped, in order
+    pub skipped: Vec<String>,
+}
+
+pub async fn stream_for_anilist(
+    registry: &ProviderRegistry,
+    mappings: &ProviderMappings,
+    episode: f32,
+    language: TranslationType,
+) -> Result<ResolvedStream> {
+    let mut skipped = Vec::new();
+
+    for info in registry.infos() {
+        match try_provider(registry, info.id, mappings, episode, language).await {
+            Ok((media_id, episode_id, source)) => {
+                println!("DEBUG: AniList {} ep {} ({}) served by {}", mappings.anilist_id, episode, language, info.id);
+                return Ok(ResolvedStream { source, served_by: info.id.to_string(), media_id, episode_id, skipped });
+            },
+            Err(e) => {
+                println!("DEBUG: Fallback skipping {}: {}", info.id, e);
+                skipped.push(e.to_string());
+            },
+        }
+    }
+
+    Err(NezukoError::not_found(
+        "fallback",
+        format!("No provider could play AniList {} episode {} ({}): {}", mappings.anilist_id, episode, language, skipped.join("; ")),
+    ))
+}
+
+async fn try_provider(
+    registry: &ProviderRegistry,
+    provider_id: &str,
+    mappings: &ProviderMappings,
+    episode: f32,
+    language: TranslationType,
+) -> Result<(MediaId, EpisodeId, VideoSource)> {
+    let provider = registry.get(provider_id)
+        .ok_or_else(|| NezukoError::unsupported(provider_id, "provider is not enabled"))?;
+
+    let media_id = match provider.media_id_from_mappings(mappings, language) {
+        Some(id) => id,
+        None => find_by_title(registry, provider_id, mappings, language).await?,
+    };
+
+    let episodes = registry.episodes(&media_id).await?;
+    let ep = episodes.iter()
+        .find(|e| (e.number - episode).abs() < 0.01)
+        .ok_or_else(|| NezukoError::not_found(provider_id, format!("no episode {} in {}", episode, media_id)))?;
+
+    let source = registry.stream(&ep.id).await?;
+    if source.status != StreamStatus::Resolved || source.url.is_empty() {
+        return Err(NezukoError::not_found(provider_id, format!("no playable source for {}", ep.id)));
+    }
+    Ok((media_id, ep.id.clone(), source))
+}
+
+/// Search by the show's titles and accept only an exact (normalised) title match
+async fn find_by_title(
+    registry: &ProviderRegistry,
+    provider_id: &str,
+    mappings: &ProviderMappings,
+    language: TranslationType,
+) -> Result<MediaId> {
+    let titles = search_titles(mappings);
+    if titles.is_empty() {
+        return Err(NezukoError::not_found(provider_id, "no mapped ID and no title to search"));
+    }
+
+    for title in &titles {
+        let results = registry.search_provider(provider_id, title).await?;
+        if let Some(id) = pick_match(&results, &titles, language) {
+            return Ok(id);
+        }
+    }
+    Err(NezukoError::not_found(provider_id, format!("no {} match for '{}'", language, titles[0])))
+}
+
+/// Romaji first (what most scrapers index), then English
+fn search_titles(mappings: &ProviderMappings) -> Vec<String> {
+    let mut titles: Vec<String> = Vec::new();
+    for key in ["x-jat", "en"] {
+        if let Some(t) = mappings.show_titles.get(key) {
+            if !titles.contains(t) {
+                titles.push(t.clone());
+            }
+        }
+    }
+    titles
+}
+
+fn pick_match(results: &[AnimeResult], titles: &[String], language: TranslationType) -> Option<MediaId> {
+    let wanted: Vec<String> = titles.iter().map(|t| normalize_title(t)).collect();
+    results.iter()
+        .filter(|r| wanted.contains(&normalize_title(&r.title)))
+        .find_map(|r| r.variant_ids.get(language.as_str()).cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn result(title: &str, slug: &str, modes: &[&str]) -> AnimeResult {
+        let id = MediaId::Anitaku { slug: slug.to_string() };
+        AnimeResult {
+            id: id.clone(),
+            title: title.to_string(),
+            url: String::new(),
+            image: String::new(),
+            release_date: None,
+            language: None,
+            is_multi_audio: false,
+            available_languages: Vec::new(),
+            provider: "anitaku".to_string(),
+            episode_count: None,
+            variant_ids: modes.iter().map(|m| (m.to_string(), id.clone())).collect(),
+            alt_titles: Vec::new(),
+            matches: Vec::new(),
+            match_confidence: None,
+            relevance: None,
+            format: None,
+            rating: None,
+        }
+    }
+
+    #[test]
+    fn test_pick_match_requires_exact_title_and_language() {
+        let results = vec![
+            result("Sousou no Frieren: Marumaru", "frieren-special", &["sub"]),
+            result("Sousou no Frieren", "sousou-no-frieren", &["sub"]),
+            result("Sousou no Frieren (Dub)", "sousou-no-frieren-dub", &["dub"]),
+        ];
+        let titles = vec!["Sousou no Frieren".to_string(), "Frieren: Beyond Journey's End".to_string()];
+
+        assert_eq!(
+            pick_match(&results, &titles, TranslationType::Sub),
+            Some(MediaId::Anitaku { slug: "sousou-no-frieren".to_string() })
+        );
+        assert_eq!(
+            pick_match(&results, &titles, TranslationType::Dub),
+            Some(MediaId::Anitaku { slug: "sousou-no-frieren-dub".to_string() })
+        );
+        assert_eq!(pick_match(&results[..1], &titles, TranslationType::Sub), None);
+    }
+
+    #[test]
+    fn test_search_titles_prefers_romaji() {
+        let mappings = ProviderMappings {
+            show_titles: HashMap::from([
+                ("en".to_string(), "Frieren: Beyond Journey's End".to_string()),
+                ("x-jat".to_string(), "Sousou no Frieren".to_string()),
+                ("ja".to_string(), "葬送のフリーレン".to_string()),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(search_titles(&mappings), vec!["Sousou no Frieren", "Frieren: Beyond Journey's End"]);
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use crate::error::NezukoError;
+
+/// Consecutive failures that open a provider's circuit
+const FAILURE_THRESHOLD: u32 = 3;
+/// How long an open circuit rejects calls before letting one probe through
+const OPEN_COOLDOWN: Duration = Duration::from_secs(30);
+/// Weight of the newest sample in the latency moving average
+const LATENCY_ALPHA: f64 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CircuitState {
+    Closed,
+    Open { since: Instant },
+    /// Cooldown elapsed; one probe call is in flight and everything else is still rejected.
+    /// A probe that never reports back is replaced after another cooldown.
+    HalfOpen { since: Instant },
+}
+
+#[derive(Debug)]
+struct ProviderHealth {
+    state: CircuitState,
+    successes: u64,
+    failures: u64,
+    consecutive_failures: u32,
+    avg_latency_ms: Option<f64>,
+    last_error: Option<String>,
+}
+
+impl Default for ProviderHealth {
+    fn default() -> Self {
+        Self {
+            state: CircuitState::Closed,
+            successes: 0,
+            failures: 0,
+            consecutive_failures: 0,
+            avg_latency_ms: None,
+            last_error: None,
+        }
+    }
+}
+
+/// Health of one provider as shown in the status UI
+#[derive(Serialize, Debug, Clone)]
+pub struct HealthReport {
+    pub provider: String,
+    pub state: &'static str, // "closed", "open", "half_open"
+    pub successes: u64,
+    pub failures: u64,
+    pub success_rate: Option<f64>,
+    pub avg_latency_ms: Option<u64>,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    /// Seconds until an open circuit lets a probe through
+    pub retry_in_secs: Option<u64>,
+}
+
+/// Success/failure/latency bookkeeping and a circuit breaker per provider
+#[derive(Default)]
+pub struct HealthMonitor {
+    providers: Mutex<HashMap<String, ProviderHealth>>,
+}
+
+impl HealthMonitor {
+    /// Whether a call to `provider` may go out now. An open circuit whose cooldown
+    /// has elapsed admits exactly one probe (half-open).
+    pub fn allow(&self, provider: &str) -> bool {
+        self.allow_at(provider, Instant::now())
+    }
+
+    fn allow_at(&self, provider: &str, now: Instant) -> bool {
+        let mut providers = self.providers.lock().unwrap();
+        let health = providers.entry(provider.to_string()).or_default();
+        match health.state {
+            CircuitState::Closed => true,
+            CircuitState::Open { since } | CircuitState::HalfOpen { since }
+                if now.duration_since(since) >= OPEN_COOLDOWN =>
+            {
+                println!("DEBUG: {} circuit half-open, probing", provider);
+                health.state = CircuitState::HalfOpen { since: now };
+                true
+            },
+            CircuitState::Open { .. } | CircuitState::HalfOpen { .. } => false,
+        }
+    }
+
+    /// Record the outcome of a call that `allow` let through
+    pub fn record<T>(&self, provider: &str, latency: Duration, result: &Result<T, NezukoError>) {
+        self.record_at(provider, latency, result, Instant::now())
+    }
+
+    fn record_at<T>(&self, provider: &str, latency: Duration, result: &Result<T, NezukoError>, now: Instant) {
+        let mut providers = self.providers.lock().unwrap();
+        let health = providers.entry(provider.to_string()).or_default();
+
+        let sample = latency.as_secs_f64() * 1000.0;
+        health.avg_latency_ms = Some(match health.avg_latency_ms {
+            Some(avg) => avg + LATENCY_ALPHA * (sample - avg),
+            None => sample,
+        });
+
+        match result {
+            Err(e) if counts_as_failure(e) => {
+                health.failures += 1;
+                health.consecutive_failures += 1;
+                health.last_error = Some(e.to_string());
+
+                let reopen = matches!(health.state, CircuitState::HalfOpen { .. })
+                    || health.consecutive_failures >= FAILURE_THRESHOLD;
+                if reopen && !matches!(health.state, CircuitState::Open { .. }) {
+                    println!("DEBUG: {} circuit opened after {} failures", provider, health.consecutive_failures);
+                    health.state = CircuitState::Open { since: now };
+                }
+            },
+            // Successes and "not on this provider" answers both prove it is up
+            _ => {
+                health.successes += 1;
+                health.consecutive_failures = 0;
+                if health.state != CircuitState::Closed {
+                    println!("DEBUG: {} circuit closed", provider);
+                }
+                health.state = CircuitState::Closed;
+            },
+        }
+    }
+
+    pub fn report(&self, provider: &str) -> HealthReport {
+        self.report_at(provider, Instant::now())
+    }
+
+    fn report_at(&self, provider: &str, now: Instant) -> HealthReport {
+        let providers = self.providers.lock().unwrap();
+        let default = ProviderHealth::default();
+        let health = providers.get(provider).unwrap_or(&default);
+
+        let total = health.successes + health.failures;
+        let (state, retry_in_secs) = match health.state {
+            CircuitState::Closed => ("closed", None),
+            CircuitState::HalfOpen { .. } => ("half_open", None),
+            CircuitState::Open { since } => {
+                let remaining = OPEN_COOLDOWN.saturating_sub(now.duration_since(since));
+                ("open", Some(remaining.as_secs()))
+            },
+        };
+
+        HealthReport {
+            provider: provider.to_string(),
+            state,
+            successes: health.successes,
+            failures: health.failures,
+            success_rate: (total > 0).then(|| health.successes as f64 / total as f64),
+            avg_latency_ms: health.avg_latency_ms.map(|ms| ms.round() as u64),
+            consecutive_failures: health.consecutive_failures,
+            last_error: health.last_error.clone(),
+            retry_in_secs,
+        }
+    }
+}
+
+/// NotFound / Unsupported mean the provider answered correctly, so they don't trip the breaker
+fn counts_as_failure(err: &NezukoError) -> bool {
+    !matches!(err, NezukoError::NotFound { .. } | NezukoError::Unsupported { .. })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fail() -> Result<(), NezukoError> {
+        Err(NezukoError::network("hianime", "connection refused"))
+    }
+
+    #[test]
+    fn test_circuit_opens_and_recovers_through_probe() {
+        let monitor = HealthMonitor::default();
+        let t0 = Instant::now();
+        let ms = Duration::from_millis(100);
+
+        for _ in 0..FAILURE_THRESHOLD {
+            assert!(monitor.allow_at("hianime", t0));
+            monitor.record_at("hianime", ms, &fail(), t0);
+        }
+        assert!(!monitor.allow_at("hianime", t0 + Duration::from_secs(1)));
+        assert_eq!(monitor.report_at("hianime", t0).state, "open");
+
+        // After the cooldown a single probe goes through
+        let later = t0 + OPEN_COOLDOWN;
+        assert!(monitor.allow_at("hianime", later));
+        assert!(!monitor.allow_at("hianime", later));
+
+        // Failed probe re-opens, successful one closes
+        monitor.record_at("hianime", ms, &fail(), later);
+        assert!(!monitor.allow_at("hianime", later));
+        let much_later = later + OPEN_COOLDOWN;
+        assert!(monitor.allow_at("hianime", much_later));
+        monitor.record_at("hianime", ms, &Ok::<(), NezukoError>(()), much_later);
+        assert!(monitor.allow_at("hianime", much_later));
+
+        let report = monitor.report_at("hianime", much_later);
+        assert_eq!(report.state, "closed");
+        assert_eq!(report.failures, 4);
+        assert_eq!(report.successes, 1);
+        assert_eq!(report.avg_latency_ms, Some(100));
+    }
+
+    #[test]
+    fn test_not_found_does_not_trip_breaker() {
+        let monitor = HealthMonitor::default();
+        let t0 = Instant::now();
+        for _ in 0..10 {
+            let res: Result<(), NezukoError> = Err(NezukoError::not_found("anitaku", "no such show"));
+            monitor.record_at("anitaku", Duration::from_millis(50), &res, t0);
+        }
+        assert!(monitor.allow_at("anitaku", t0));
+        assert_eq!(monitor.report_at("anitaku", t0).success_rate, Some(1.0));
+        assert_eq!(monitor.report_at("allanime", t0).success_rate, None);
+    }
+}
@@ -0,0 +1,989 @@
+use std::fmt::{self, Display, Write as _};
+use url::Url;
+use crate::anime::{HlsVariant, VideoSource};
+use crate::error::{NezukoError, Result};
+use crate::http::{self, RequestExt};
+
+// ============================================================
+// HLS playlists
+// ------------------------------------------------------------
+// Master and media playlists are parsed into typed structures
+// and written back out, so the proxy can resolve and rewrite
+// every URI in one place. Tags this parser doesn't model (LL-HLS,
+// DATERANGE, vendor tags) are carried verbatim. Sources only hand
+// the player a master URL: the backend reads it to list variants
+// and, when a quality is requested, the proxy serves a master
+// reduced to that one variant (`&quality=720p`).
+// ============================================================
+
+const PROVIDER: &str = "hls";
+
+/// What a caller asked for: "best"/"auto", "lowest", a height ("720p", "720") or, for
+/// masters without RESOLUTION, a bandwidth ("800k"). Every variant label parses back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QualityPreference {
+    Best,
+    Lowest,
+    /// Exact height if offered, otherwise the closest one below it, otherwise the smallest above
+    Height(u32),
+    /// Kilobits per second; the variant with the nearest bandwidth
+    Kbps(u64),
+}
+
+impl QualityPreference {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "best" | "auto" | "highest" => Some(QualityPreference::Best),
+            "lowest" | "worst" => Some(QualityPreference::Lowest),
+            other => match other.strip_suffix('k') {
+                Some(kbps) => kbps.parse().ok().map(QualityPreference::Kbps),
+                None => other.trim_end_matches('p').parse().ok().map(QualityPreference::Height),
+            },
+        }
+    }
+}
+
+// ============================================================
+// Playlist model
+// ============================================================
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Playlist {
+    Master(MasterPlaylist),
+    Media(MediaPlaylist),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MasterPlaylist {
+    pub version: Option<u32>,
+    pub independent_segments: bool,
+    pub start: Option<Start>,
+    pub session_data: Vec<SessionData>,
+    pub session_keys: Vec<Key>,
+    pub renditions: Vec<Rendition>,
+    pub variants: Vec<VariantStream>,
+    pub iframe_variants: Vec<VariantStream>,
+    /// Tags this parser doesn't model, verbatim
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MediaPlaylist {
+    pub version: Option<u32>,
+    pub target_duration: Option<u64>,
+    pub media_sequence: Option<u64>,
+    pub discontinuity_sequence: Option<u64>,
+    pub playlist_type: Option<String>,
+    pub iframes_only: bool,
+    pub independent_segments: bool,
+    pub start: Option<Start>,
+    pub end_list: bool,
+    pub segments: Vec<Segment>,
+    /// Unmodelled tags before the first segment (e.g. EXT-X-SERVER-CONTROL), verbatim
+    pub tags: Vec<String>,
+    /// Tags after the last segment (e.g. EXT-X-PRELOAD-HINT), verbatim
+    pub trailing_tags: Vec<String>,
+}
+
+/// A media segment and the tags written directly before it. KEY and MAP apply to every
+/// following segment until replaced; they sit on the segment where they changed.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Segment {
+    pub uri: String,
+    pub duration: f64,
+    pub title: String,
+    pub byte_range: Option<ByteRange>,
+    pub discontinuity: bool,
+    pub keys: Vec<Key>,
+    pub map: Option<Map>,
+    pub program_date_time: Option<String>,
+    /// Unmodelled tags (EXT-X-DATERANGE, EXT-X-PART, EXT-X-GAP...), verbatim
+    pub tags: Vec<String>,
+}
+
+/// `<length>[@<offset>]`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ByteRange {
+    pub length: u64,
+    pub offset: Option<u64>,
+}
+
+/// EXT-X-KEY / EXT-X-SESSION-KEY
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Key {
+    pub method: String,
+    pub uri: Option<String>,
+    pub iv: Option<String>,
+    pub key_format: Option<String>,
+    pub key_format_versions: Option<String>,
+    pub other: Vec<(String, String)>,
+}
+
+/// EXT-X-MAP (fMP4 init segment)
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Map {
+    pub uri: String,
+    pub byte_range: Option<ByteRange>,
+    pub other: Vec<(String, String)>,
+}
+
+/// EXT-X-START
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Start {
+    pub time_offset: f64,
+    pub precise: Option<bool>,
+    pub other: Vec<(String, String)>,
+}
+
+/// EXT-X-SESSION-DATA
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SessionData {
+    pub data_id: String,
+    pub value: Option<String>,
+    pub uri: Option<String>,
+    pub language: Option<String>,
+    pub other: Vec<(String, String)>,
+}
+
+/// EXT-X-MEDIA: an alternative audio, video, subtitle or caption rendition
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Rendition {
+    pub media_type: String,
+    pub group_id: String,
+    pub name: String,
+    pub uri: Option<String>,
+    pub language: Option<String>,
+    pub assoc_language: Option<String>,
+    pub default: Option<bool>,
+    pub autoselect: Option<bool>,
+    pub forced: Option<bool>,
+    pub instream_id: Option<String>,
+    pub characteristics: Option<String>,
+    pub channels: Option<String>,
+    pub other: Vec<(String, String)>,
+}
+
+/// EXT-X-STREAM-INF with its URI line, or EXT-X-I-FRAME-STREAM-INF with its URI attribute
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct VariantStream {
+    pub uri: String,
+    pub bandwidth: u64,
+    pub average_bandwidth: Option<u64>,
+    pub resolution: Option<(u32, u32)>,
+    pub codecs: Option<String>,
+    pub frame_rate: Option<f64>,
+    pub hdcp_level: Option<String>,
+    pub audio: Option<String>,
+    pub video: Option<String>,
+    pub subtitles: Option<String>,
+    /// Group id, or "NONE" (written unquoted)
+    pub closed_captions: Option<String>,
+    pub other: Vec<(String, String)>,
+}
+
+impl VariantStream {
+    pub fn to_variant(&self) -> HlsVariant {
+        let (width, height) = self.resolution.unzip();
+        HlsVariant {
+            label: height.map(|h| format!("{}p", h)).unwrap_or_else(|| format!("{}k", self.bandwidth / 1000)),
+            uri: self.uri.clone(),
+            bandwidth: self.bandwidth,
+            average_bandwidth: self.average_bandwidth,
+            width,
+            height,
+            codecs: self.codecs.clone(),
+            frame_rate: self.frame_rate.map(|f| f as f32),
+            audio_group: self.audio.clone(),
+        }
+    }
+}
+
+// ============================================================
+// Parsing
+// ============================================================
+
+impl Playlist {
+    pub fn parse(body: &str) -> Result<Playlist> {
+        let body = body.trim_start_matches('\u{feff}');
+        let mut lines = body.lines().map(str::trim).filter(|l| !l.is_empty()).peekable();
+        if !lines.peek().is_some_and(|l| l.starts_with("#EXTM3U")) {
+            return Err(NezukoError::parse(PROVIDER, "playlist does not start with #EXTM3U"));
+        }
+        if is_master(body) {
+            Ok(Playlist::Master(MasterPlaylist::parse(lines)))
+        } else {
+            Ok(Playlist::Media(MediaPlaylist::parse(lines)))
+        }
+    }
+
+    /// Replace every URI (segments, variants, renditions, keys, maps, and URI attributes of
+    /// unmodelled tags) with `f(uri)`
+    pub fn map_uris(&mut self, mut f: impl FnMut(&str) -> String) {
+        match self {
+            Playlist::Master(master) => master.map_uris(&mut f),
+            Playlist::Media(media) => media.map_uris(&mut f),
+        }
+    }
+
+    /// Make every URI absolute. `base` should be the URL the playlist was finally served
+    /// from (after redirects), which is what relative URIs are relative to.
+    pub fn resolve_uris(&mut self, base: &Url) {
+        self.map_uris(|uri| base.join(uri).map(String::from).unwrap_or_else(|_| uri.to_string()));
+    }
+}
+
+/// Whether a playlist is a master (lists variants) rather than a media playlist (lists segments)
+pub fn is_master(body: &str) -> bool {
+    body.lines().map(str::trim_start).any(|l| {
+        l.starts_with("#EXT-X-STREAM-INF") || l.starts_with("#EXT-X-I-FRAME-STREAM-INF")
+            || l.starts_with("#EXT-X-MEDIA:") || l.starts_with("#EXT-X-SESSION-")
+    })
+}
+
+/// "#EXT-X-KEY:METHOD=NONE" -> ("#EXT-X-KEY", "METHOD=NONE")
+fn split_tag(line: &str) -> (&str, &str) {
+    line.split_once(':').unwrap_or((line, ""))
+}
+
+impl MasterPlaylist {
+    fn parse<'a>(lines: impl Iterator<Item = &'a str>) -> Self {
+        let mut master = MasterPlaylist::default();
+        let mut pending: Option<VariantStream> = None;
+
+        for line in lines {
+            if !line.starts_with('#') {
+                if let Some(mut variant) = pending.take() {
+                    variant.uri = line.to_string();
+                    master.variants.push(variant);
+                }
+                continue;
+            }
+            let (name, value) = split_tag(line);
+            match name {
+                "#EXTM3U" => {},
+                "#EXT-X-VERSION" => master.version = value.parse().ok(),
+                "#EXT-X-INDEPENDENT-SEGMENTS" => master.independent_segments = true,
+                "#EXT-X-START" => master.start = Some(Start::parse(value)),
+                "#EXT-X-SESSION-DATA" => master.session_data.push(SessionData::parse(value)),
+                "#EXT-X-SESSION-KEY" => master.session_keys.push(Key::parse(value)),
+                "#EXT-X-MEDIA" => master.renditions.push(Rendition::parse(value)),
+                "#EXT-X-STREAM-INF" => pending = Some(VariantStream::parse(value)),
+                "#EXT-X-I-FRAME-STREAM-INF" => master.iframe_variants.push(VariantStream::parse(value)),
+                // Plain comments are dropped
+                _ if line.starts_with("#EXT") => master.tags.push(line.to_string()),
+                _ => {},
+            }
+        }
+        master
+    }
+
+    fn map_uris(&mut self, f: &mut dyn FnMut(&str) -> String) {
+        for data in &mut self.session_data {
+            map_option(&mut data.uri, f);
+        }
+        for key in &mut self.session_keys {
+            map_option(&mut key.uri, f);
+        }
+        for rendition in &mut self.renditions {
+            map_option(&mut rendition.uri, f);
+        }
+        for variant in self.variants.iter_mut().chain(&mut self.iframe_variants) {
+            variant.uri = f(&variant.uri);
+        }
+        map_tags(&mut self.tags, f);
+    }
+
+    /// Keep only the variant that best fits `preference`, the renditions it references and
+    /// no I-frame variants. False (and unchanged) when there are no variants.
+    pub fn retain_variant(&mut self, preference: QualityPreference) -> bool {
+        let variants: Vec<HlsVariant> = self.variants.iter().map(VariantStream::to_variant).collect();
+        let Some(keep) = pick_variant(&variants, preference) else { return false };
+        let variant = self.variants.swap_remove(keep);
+
+        let groups = [("AUDIO", &variant.audio), ("VIDEO", &variant.video), ("SUBTITLES", &variant.subtitles), ("CLOSED-CAPTIONS", &variant.closed_captions)];
+        self.renditions.retain(|r| {
+            groups.iter()
+                .find(|(kind, _)| *kind == r.media_type)
+                .is_none_or(|(_, group)| group.as_ref() == Some(&r.group_id))
+        });
+        self.variants = vec![variant];
+        self.iframe_variants.clear();
+        true
+    }
+}
+
+impl MediaPlaylist {
+    fn parse<'a>(lines: impl Iterator<Item = &'a str>) -> Self {
+        let mut media = MediaPlaylist::default();
+        let mut pending = Segment::default();
+
+        for line in lines {
+            if !line.starts_with('#') {
+                pending.uri = line.to_string();
+                media.segments.push(std::mem::take(&mut pending));
+                continue;
+            }
+            let (name, value) = split_tag(line);
+            match name {
+                "#EXTM3U" => {},
+                "#EXT-X-VERSION" => media.version = value.parse().ok(),
+                "#EXT-X-INDEPENDENT-SEGMENTS" => media.independent_segments = true,
+                "#EXT-X-START" => media.start = Some(Start::parse(value)),
+                "#EXT-X-TARGETDURATION" => media.target_duration = value.parse().ok(),
+                "#EXT-X-MEDIA-SEQUENCE" => media.media_sequence = value.parse().ok(),
+                "#EXT-X-DISCONTINUITY-SEQUENCE" => media.discontinuity_sequence = value.parse().ok(),
+                "#EXT-X-PLAYLIST-TYPE" => media.playlist_type = Some(value.to_string()),
+                "#EXT-X-I-FRAMES-ONLY" => media.iframes_only = true,
+                "#EXT-X-ENDLIST" => media.end_list = true,
+                "#EXTINF" => {
+                    let (duration, title) = value.split_once(',').unwrap_or((value, ""));
+                    pending.duration = duration.trim().parse().unwrap_or(0.0);
+                    pending.title = title.to_string();
+                },
+                "#EXT-X-BYTERANGE" => pending.byte_range = ByteRange::parse(value),
+                "#EXT-X-DISCONTINUITY" => pending.discontinuity = true,
+                "#EXT-X-KEY" => pending.keys.push(Key::parse(value)),
+                "#EXT-X-MAP" => pending.map = Some(Map::parse(value)),
+                "#EXT-X-PROGRAM-DATE-TIME" => pending.program_date_time = Some(value.to_string()),
+                _ if !line.starts_with("#EXT") => {},
+                _ if media.segments.is_empty() && pending == Segment::default() => media.tags.push(line.to_string()),
+                _ => pending.tags.push(line.to_string()),
+            }
+        }
+
+        // Tags with no segment after them (LL-HLS hints, a final KEY) are kept as written
+        if pending != Segment::default() {
+            let mut out = String::new();
+            pending.write_tags(&mut out);
+            media.trailing_tags = out.lines().map(str::to_string).collect();
+        }
+        media
+    }
+
+    fn map_uris(&mut self, f: &mut dyn FnMut(&str) -> String) {
+        for segment in &mut self.segments {
+            segment.uri = f(&segment.uri);
+            for key in &mut segment.keys {
+                map_option(&mut key.uri, f);
+            }
+            if let Some(map) = &mut segment.map {
+                map.uri = f(&map.uri);
+            }
+            map_tags(&mut segment.tags, f);
+        }
+        map_tags(&mut self.tags, f);
+        map_tags(&mut self.trailing_tags, f);
+    }
+}
+
+impl ByteRange {
+    fn parse(value: &str) -> Option<Self> {
+        let (length, offset) = match value.split_once('@') {
+            Some((length, offset)) => (length, Some(offset.trim().parse().ok()?)),
+            None => (value, None),
+        };
+        Some(ByteRange { length: length.trim().parse().ok()?, offset })
+    }
+}
+
+impl Key {
+    fn parse(value: &str) -> Self {
+        let mut attrs = Attributes::parse(value);
+        Key {
+            method: attrs.take("METHOD").unwrap_or_default(),
+            uri: attrs.take("URI"),
+            iv: attrs.take("IV"),
+            key_format: attrs.take("KEYFORMAT"),
+            key_format_versions: attrs.take("KEYFORMATVERSIONS"),
+            other: attrs.0,
+        }
+    }
+}
+
+impl Map {
+    fn parse(value: &str) -> Self {
+        let mut attrs = Attributes::parse(value);
+        Map {
+            uri: attrs.take("URI").unwrap_or_default(),
+            byte_range: attrs.take("BYTERANGE").and_then(|b| ByteRange::parse(&b)),
+            other: attrs.0,
+        }
+    }
+}
+
+impl Start {
+    fn parse(value: &str) -> Self {
+        let mut attrs = Attributes::parse(value);
+        Start {
+            time_offset: attrs.take_parsed("TIME-OFFSET").unwrap_or(0.0),
+            precise: attrs.take_yes_no("PRECISE"),
+            other: attrs.0,
+        }
+    }
+}
+
+impl SessionData {
+    fn parse(value: &str) -> Self {
+        let mut attrs = Attributes::parse(value);
+        SessionData {
+            data_id: attrs.take("DATA-ID").unwrap_or_default(),
+            value: attrs.take("VALUE"),
+            uri: attrs.take("URI"),
+            language: attrs.take("LANGUAGE"),
+            other: attrs.0,
+        }
+    }
+}
+
+impl Rendition {
+    fn parse(value: &str) -> Self {
+        let mut attrs = Attributes::parse(value);
+        Rendition {
+            media_type: attrs.take("TYPE").unwrap_or_default(),
+            group_id: attrs.take("GROUP-ID").unwrap_or_default(),
+            name: attrs.take("NAME").unwrap_or_default(),
+            uri: attrs.take("URI"),
+            language: attrs.take("LANGUAGE"),
+            assoc_language: attrs.take("ASSOC-LANGUAGE"),
+            default: attrs.take_yes_no("DEFAULT"),
+            autoselect: attrs.take_yes_no("AUTOSELECT"),
+            forced: attrs.take_yes_no("FORCED"),
+            instream_id: attrs.take("INSTREAM-ID"),
+            characteristics: attrs.take("CHARACTERISTICS"),
+            channels: attrs.take("CHANNELS"),
+            other: attrs.0,
+        }
+    }
+}
+
+impl VariantStream {
+    fn parse(value: &str) -> Self {
+        let mut attrs = Attributes::parse(value);
+        VariantStream {
+            uri: attrs.take("URI").unwrap_or_default(),
+            bandwidth: attrs.take_parsed("BANDWIDTH").unwrap_or(0),
+            average_bandwidth: attrs.take_parsed("AVERAGE-BANDWIDTH"),
+            resolution: attrs.take("RESOLUTION")
+                .and_then(|r| r.split_once('x').and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))),
+            codecs: attrs.take("CODECS"),
+            frame_rate: attrs.take_parsed("FRAME-RATE"),
+            hdcp_level: attrs.take("HDCP-LEVEL"),
+            audio: attrs.take("AUDIO"),
+            video: attrs.take("VIDEO"),
+            subtitles: attrs.take("SUBTITLES"),
+            closed_captions: attrs.take("CLOSED-CAPTIONS"),
+            other: attrs.0,
+        }
+    }
+}
+
+/// `KEY=value,KEY="quoted, value"` attribute list. Values keep their quotes until taken,
+/// so whatever isn't taken can be written back unchanged.
+struct Attributes(Vec<(String, String)>);
+
+impl Attributes {
+    fn parse(list: &str) -> Self {
+        let mut attrs = Vec::new();
+        let mut rest = list;
+        while let Some((key, after)) = rest.split_once('=') {
+            let end = match after.strip_prefix('"') {
+                Some(quoted) => quoted.find('"').map(|i| i + 2).unwrap_or(after.len()),
+                None => after.find(',').unwrap_or(after.len()),
+            };
+            attrs.push((key.trim().to_string(), after[..end].to_string()));
+            rest = after[end..].trim_start_matches(',');
+        }
+        Attributes(attrs)
+    }
+
+    /// Remove an attribute, unquoted
+    fn take(&mut self, key: &str) -> Option<String> {
+        let pos = self.0.iter().position(|(k, _)| k == key)?;
+        let (_, value) = self.0.remove(pos);
+        Some(value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(&value).to_string())
+    }
+
+    fn take_parsed<T: std::str::FromStr>(&mut self, key: &str) -> Option<T> {
+        self.take(key)?.parse().ok()
+    }
+
+    fn take_yes_no(&mut self, key: &str) -> Option<bool> {
+        self.take(key).map(|v| v == "YES")
+    }
+}
+
+fn map_option(uri: &mut Option<String>, f: &mut dyn FnMut(&str) -> String) {
+    if let Some(uri) = uri {
+        *uri = f(uri);
+    }
+}
+
+/// Rewrite `URI="..."` attributes inside verbatim tags
+fn map_tags(tags: &mut [String], f: &mut dyn FnMut(&str) -> String) {
+    for tag in tags {
+        if !tag.contains("URI=\"") {
+            continue;
+        }
+        let mut out = String::new();
+        let mut rest = tag.as_str();
+        while let Some(pos) = rest.find("URI=\"") {
+            let (before, after) = rest.split_at(pos + 5);
+            let end = after.find('"').unwrap_or(after.len());
+            out.push_str(before);
+            out.push_str(&f(&after[..end]));
+            rest = &after[end..];
+        }
+        out.push_str(rest);
+        *tag = out;
+    }
+}
+
+// ============================================================
+// Writing
+// ============================================================
+
+/// Builds an attribute list in a fixed order; unset values are skipped
+#[derive(Default)]
+struct AttributeWriter(Vec<String>);
+
+impl AttributeWriter {
+    fn quoted(&mut self, key: &str, value: Option<&str>) {
+        if let Some(value) = value {
+            self.0.push(format!("{}=\"{}\"", key, value));
+        }
+    }
+
+    fn plain(&mut self, key: &str, value: Option<impl Display>) {
+        if let Some(value) = value {
+            self.0.push(format!("{}={}", key, value));
+        }
+    }
+
+    fn yes_no(&mut self, key: &str, value: Option<bool>) {
+        self.plain(key, value.map(|v| if v { "YES" } else { "NO" }));
+    }
+
+    fn other(&mut self, other: &[(String, String)]) {
+        self.0.extend(other.iter().map(|(k, v)| format!("{}={}", k, v)));
+    }
+
+    fn finish(self) -> String {
+        self.0.join(",")
+    }
+}
+
+impl Display for ByteRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.offset {
+            Some(offset) => write!(f, "{}@{}", self.length, offset),
+            None => write!(f, "{}", self.length),
+        }
+    }
+}
+
+impl Key {
+    fn attributes(&self) -> String {
+        let mut w = AttributeWriter::default();
+        w.plain("METHOD", Some(&self.method));
+        w.quoted("URI", self.uri.as_deref());
+        w.plain("IV", self.iv.as_deref());
+        w.quoted("KEYFORMAT", self.key_format.as_deref());
+        w.quoted("KEYFORMATVERSIONS", self.key_format_versions.as_deref());
+        w.other(&self.other);
+        w.finish()
+    }
+}
+
+impl Start {
+    fn attributes(&self) -> String {
+        let mut w = AttributeWriter::default();
+        w.plain("TIME-OFFSET", Some(self.time_offset));
+        w.yes_no("PRECISE", self.precise);
+        w.other(&self.other);
+        w.finish()
+    }
+}
+
+impl VariantStream {
+    fn attributes(&self, with_uri: bool) -> String {
+        let mut w = AttributeWriter::default();
+        w.plain("BANDWIDTH", Some(self.bandwidth));
+        w.plain("AVERAGE-BANDWIDTH", self.average_bandwidth);
+        w.plain("RESOLUTION", self.resolution.map(|(width, height)| format!("{}x{}", width, height)));
+        w.quoted("CODECS", self.codecs.as_deref());
+        w.plain("FRAME-RATE", self.frame_rate);
+        w.plain("HDCP-LEVEL", self.hdcp_level.as_deref());
+        w.quoted("AUDIO", self.audio.as_deref());
+        w.quoted("VIDEO", self.video.as_deref());
+        w.quoted("SUBTITLES", self.subtitles.as_deref());
+        match self.closed_captions.as_deref() {
+            Some("NONE") => w.plain("CLOSED-CAPTIONS", Some("NONE")),
+            other => w.quoted("CLOSED-CAPTIONS", other),
+        }
+        w.other(&self.other);
+        if with_uri {
+            w.quoted("URI", Some(&self.uri));
+        }
+        w.finish()
+    }
+}
+
+impl Segment {
+    /// Everything before the URI line
+    fn write_tags(&self, out: &mut String) {
+        if self.discontinuity {
+            out.push_str("#EXT-X-DISCONTINUITY\n");
+        }
+        for key in &self.keys {
+            let _ = writeln!(out, "#EXT-X-KEY:{}", key.attributes());
+        }
+        if let Some(map) = &self.map {
+            let mut w = AttributeWriter::default();
+            w.quoted("URI", Some(&map.uri));
+            w.quoted("BYTERANGE", map.byte_range.map(|b| b.to_string()).as_deref());
+            w.other(&map.other);
+            let _ = writeln!(out, "#EXT-X-MAP:{}", w.finish());
+        }
+        if let Some(time) = &self.program_date_time {
+            let _ = writeln!(out, "#EXT-X-PROGRAM-DATE-TIME:{}", time);
+        }
+        for tag in &self.tags {
+            let _ = writeln!(out, "{}", tag);
+        }
+        if !self.uri.is_empty() {
+            let _ = writeln!(out, "#EXTINF:{},{}", self.duration, self.title);
+        }
+        if let Some(range) = self.byte_range {
+            let _ = writeln!(out, "#EXT-X-BYTERANGE:{}", range);
+        }
+    }
+}
+
+fn write_common(f: &mut fmt::Formatter<'_>, version: Option<u32>, independent_segments: bool, start: &Option<Start>) -> fmt::Result {
+    writeln!(f, "#EXTM3U")?;
+    if let Some(version) = version {
+        writeln!(f, "#EXT-X-VERSION:{}", version)?;
+    }
+    if independent_segments {
+        writeln!(f, "#EXT-X-INDEPENDENT-SEGMENTS")?;
+    }
+    if let Some(start) = start {
+        writeln!(f, "#EXT-X-START:{}", start.attributes())?;
+    }
+    Ok(())
+}
+
+impl Display for MasterPlaylist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_common(f, self.version, self.independent_segments, &self.start)?;
+        for tag in &self.tags {
+            writeln!(f, "{}", tag)?;
+        }
+        for data in &self.session_data {
+            let mut w = AttributeWriter::default();
+            w.quoted("DATA-ID", Some(&data.data_id));
+            w.quoted("VALUE", data.value.as_deref());
+            w.quoted("URI", data.uri.as_deref());
+            w.quoted("LANGUAGE", data.language.as_deref());
+            w.other(&data.other);
+            writeln!(f, "#EXT-X-SESSION-DATA:{}", w.finish())?;
+        }
+        for key in &self.session_keys {
+            writeln!(f, "#EXT-X-SESSION-KEY:{}", key.attributes())?;
+        }
+        for r in &self.renditions {
+            let mut w = AttributeWriter::default();
+            w.plain("TYPE", Some(&r.media_type));
+            w.quoted("GROUP-ID", Some(&r.group_id));
+            w.quoted("LANGUAGE", r.language.as_deref());
+            w.quoted("ASSOC-LANGUAGE", r.assoc_language.as_deref());
+            w.quoted("NAME", Some(&r.name));
+            w.yes_no("DEFAULT", r.default);
+            w.yes_no("AUTOSELECT", r.autoselect);
+            w.yes_no("FORCED", r.forced);
+            w.quoted("INSTREAM-ID", r.instream_id.as_deref());
+            w.quoted("CHARACTERISTICS", r.characteristics.as_deref());
+            w.quoted("CHANNELS", r.channels.as_deref());
+            w.other(&r.other);
+            w.quoted("URI", r.uri.as_deref());
+            writeln!(f, "#EXT-X-MEDIA:{}", w.finish())?;
+        }
+        for variant in &self.variants {
+            writeln!(f, "#EXT-X-STREAM-INF:{}", variant.attributes(false))?;
+            writeln!(f, "{}", variant.uri)?;
+        }
+        for variant in &self.iframe_variants {
+            writeln!(f, "#EXT-X-I-FRAME-STREAM-INF:{}", variant.attributes(true))?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for MediaPlaylist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_common(f, self.version, self.independent_segments, &self.start)?;
+        if let Some(duration) = self.target_duration {
+            writeln!(f, "#EXT-X-TARGETDURATION:{}", duration)?;
+        }
+        if let Some(sequence) = self.media_sequence {
+            writeln!(f, "#EXT-X-MEDIA-SEQUENCE:{}", sequence)?;
+        }
+        if let Some(sequence) = self.discontinuity_sequence {
+            writeln!(f, "#EXT-X-DISCONTINUITY-SEQUENCE:{}", sequence)?;
+        }
+        if let Some(kind) = &self.playlist_type {
+            writeln!(f, "#EXT-X-PLAYLIST-TYPE:{}", kind)?;
+        }
+        if self.iframes_only {
+            writeln!(f, "#EXT-X-I-FRAMES-ONLY")?;
+        }
+        for tag in &self.tags {
+            writeln!(f, "{}", tag)?;
+        }
+        let mut out = String::new();
+        for segment in &self.segments {
+            segment.write_tags(&mut out);
+            out.push_str(&segment.uri);
+            out.push('\n');
+        }
+        f.write_str(&out)?;
+        for tag in &self.trailing_tags {
+            writeln!(f, "{}", tag)?;
+        }
+        if self.end_list {
+            writeln!(f, "#EXT-X-ENDLIST")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Playlist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Playlist::Master(master) => master.fmt(f),
+            Playlist::Media(media) => media.fmt(f),
+        }
+    }
+}
+
+// ============================================================
+// Variant selection
+// ============================================================
+
+/// Variants of a master playlist, URIs resolved against `base_url`, in playlist order
+pub fn parse_master(body: &str, base_url: &str) -> Vec<HlsVariant> {
+    let Ok(mut playlist) = Playlist::parse(body) else { return Vec::new() };
+    if let Ok(base) = Url::parse(base_url) {
+        playlist.resolve_uris(&base);
+    }
+    match playlist {
+        Playlist::Master(master) => master.variants.iter().map(VariantStream::to_variant).collect(),
+        Playlist::Media(_) => Vec::new(),
+    }
+}
+
+/// Index of the variant that best fits `preference`
+pub fn pick_variant(variants: &[HlsVariant], preference: QualityPreference) -> Option<usize> {
+    let by_size = |v: &HlsVariant| (v.height.unwrap_or(0), v.bandwidth);
+    let indexed = variants.iter().enumerate();
+    match preference {
+        QualityPreference::Best => indexed.max_by_key(|(_, v)| by_size(v)).map(|(i, _)| i),
+        QualityPreference::Lowest => indexed.min_by_key(|(_, v)| by_size(v)).map(|(i, _)| i),
+        QualityPreference::Height(target) => {
+            let (below, above): (Vec<_>, Vec<_>) = indexed.partition(|(_, v)| v.height.unwrap_or(0) <= target);
+            below.into_iter().max_by_key(|(_, v)| by_size(v))
+                .or_else(|| above.into_iter().min_by_key(|(_, v)| by_size(v)))
+                .map(|(i, _)| i)
+        },
+        QualityPreference::Kbps(target) => indexed
+            .min_by_key(|(_, v)| (v.bandwidth / 1000).abs_diff(target))
+            .map(|(i, _)| i),
+    }
+}
+
+/// Fetch the master behind a source and list its variants on it. With a preference, the
+/// source URL is pinned to the chosen variant through the proxy's `quality` parameter.
+/// Sources that aren't proxied HLS masters are returned unchanged.
+pub async fn inspect_source(mut source: VideoSource, preference: Option<QualityPreference>) -> Result<VideoSource> {
+    if !source.is_m3u8 {
+        return Ok(source);
+    }
+    let Some((upstream, headers)) = crate::proxy::proxy_target(&source.url) else {
+        return Ok(source);
+    };
+
+    let mut request = http::client().get(&upstream);
+    for (key, value) in &headers {
+        request = request.header(key, value);
+    }
+    let res = NezukoError::check_status(PROVIDER, request.send_with_retry(PROVIDER).await?)?;
+    // Relative variant URIs resolve against where the master actually came from
+    let final_url = res.url().to_string();
+    let body = res.text().await.map_err(|e| NezukoError::from_reqwest(PROVIDER, e))?;
+
+    source.variants = parse_master(&body, &final_url);
+    println!("DEBUG: HLS master {} has {} variants", upstream, source.variants.len());
+
+    if let Some(preference) = preference {
+        if let Some(i) = pick_variant(&source.variants, preference) {
+            source.quality = source.variants[i].label.clone();
+            source.url = format!("{}&quality={}", source.url, source.variants[i].label);
+        }
+    }
+    Ok(source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MASTER: &str = "#EXTM3U\n\
+        #EXT-X-VERSION:3\n\
+        #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud-hi\",NAME=\"Japanese\",DEFAULT=YES,URI=\"audio/hi.m3u8\"\n\
+        #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud-lo\",NAME=\"Japanese\",DEFAULT=YES,URI=\"audio/lo.m3u8\"\n\
+        #EXT-X-STREAM-INF:BANDWIDTH=5000000,AVERAGE-BANDWIDTH=4500000,RESOLUTION=1920x1080,CODECS=\"avc1.640028,mp4a.40.2\",FRAME-RATE=23.976,AUDIO=\"aud-hi\"\n\
+        1080/index.m3u8\n\
+        #EXT-X-STREAM-INF:BANDWIDTH=2800000,RESOLUTION=1280x720,CODECS=\"avc1.64001f,mp4a.40.2\",AUDIO=\"aud-hi\"\n\
+        720/index.m3u8\n\
+        #EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360,AUDIO=\"aud-lo\"\n\
+        https://cdn2.example/360/index.m3u8?token=abc\n\
+        #EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=90000,URI=\"iframes.m3u8\"\n";
+
+    #[test]
+    fn test_parse_master_variants() {
+        let variants = parse_master(MASTER, "https://cdn.example/hls/ep1/master.m3u8");
+        assert_eq!(variants.len(), 3);
+
+        let top = &variants[0];
+        assert_eq!(top.uri, "https://cdn.example/hls/ep1/1080/index.m3u8");
+        assert_eq!((top.width, top.height), (Some(1920), Some(1080)));
+        assert_eq!(top.bandwidth, 5_000_000);
+        assert_eq!(top.average_bandwidth, Some(4_500_000));
+        assert_eq!(top.codecs.as_deref(), Some("avc1.640028,mp4a.40.2"));
+        assert_eq!(top.frame_rate, Some(23.976));
+        assert_eq!(top.audio_group.as_deref(), Some("aud-hi"));
+        assert_eq!(top.label, "1080p");
+        assert_eq!(variants[2].uri, "https://cdn2.example/360/index.m3u8?token=abc");
+
+        assert_eq!(pick_variant(&variants, QualityPreference::Best), Some(0));
+        assert_eq!(pick_variant(&variants, QualityPreference::Lowest), Some(2));
+        assert_eq!(pick_variant(&variants, QualityPreference::parse("720p").unwrap()), Some(1));
+        assert_eq!(pick_variant(&variants, QualityPreference::Height(480)), Some(2));
+        assert_eq!(pick_variant(&variants, QualityPreference::Height(240)), Some(2));
+        assert!(!is_master("#EXTM3U\n#EXTINF:10,\nseg1.ts\n"));
+    }
+
+    #[test]
+    fn test_single_variant_master_keeps_its_audio() {
+        let Ok(Playlist::Master(mut master)) = Playlist::parse(MASTER) else { panic!("not a master") };
+        assert!(master.retain_variant(QualityPreference::Height(360)));
+        assert_eq!(master.to_string(), "#EXTM3U\n\
+            #EXT-X-VERSION:3\n\
+            #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud-lo\",NAME=\"Japanese\",DEFAULT=YES,URI=\"audio/lo.m3u8\"\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360,AUDIO=\"aud-lo\"\n\
+            https://cdn2.example/360/index.m3u8?token=abc\n");
+        assert!(matches!(Playlist::parse("#EXTM3U\n#EXTINF:10,\nseg1.ts\n"), Ok(Playlist::Media(_))));
+    }
+
+    #[test]
+    fn test_labels_without_resolution_round_trip() {
+        let master = "#EXTM3U\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=2400000\nhigh.m3u8\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=800000\nlow.m3u8\n";
+        let variants = parse_master(master, "https://cdn.example/hls/ep1/master.m3u8");
+        assert_eq!(variants.iter().map(|v| v.label.as_str()).collect::<Vec<_>>(), vec!["2400k", "800k"]);
+        assert_eq!(QualityPreference::parse("800k"), Some(QualityPreference::Kbps(800)));
+
+        for (i, variant) in variants.iter().enumerate() {
+            let preference = QualityPreference::parse(&variant.label).unwrap();
+            assert_eq!(pick_variant(&variants, preference), Some(i));
+        }
+        let Ok(Playlist::Master(mut pinned)) = Playlist::parse(master) else { panic!("not a master") };
+        assert!(pinned.retain_variant(QualityPreference::parse("800k").unwrap()));
+        assert_eq!(pinned.variants[0].uri, "low.m3u8");
+    }
+
+    const MASTER_FIXTURE: &str = include_str!("../tests/fixtures/hls/master.m3u8");
+    const MEDIA_FIXTURE: &str = include_str!("../tests/fixtures/hls/media.m3u8");
+    const LIVE_FIXTURE: &str = include_str!("../tests/fixtures/hls/live.m3u8");
+
+    fn round_trips(playlist: &Playlist) {
+        assert_eq!(&Playlist::parse(&playlist.to_string()).unwrap(), playlist);
+    }
+
+    #[test]
+    fn test_master_fixture_parses_and_resolves() {
+        let playlist = Playlist::parse(MASTER_FIXTURE).unwrap();
+        round_trips(&playlist);
+        let Playlist::Master(master) = &playlist else { panic!("not a master") };
+        assert_eq!((master.version, master.independent_segments), (Some(6), true));
+        assert_eq!(master.start.as_ref().map(|s| (s.time_offset, s.precise)), Some((-12.5, Some(true))));
+        assert_eq!(master.session_data[0].value.as_deref(), Some("Episode 1"));
+        assert_eq!(master.session_keys[0].key_format.as_deref(), Some("identity"));
+        assert_eq!(master.renditions.len(), 3);
+        assert_eq!(master.renditions[1].name, "English, dub");
+        assert_eq!(master.renditions[1].default, Some(false));
+        assert_eq!(master.variants[0].closed_captions.as_deref(), Some("NONE"));
+        assert_eq!(master.variants[1].other, vec![("X-VENDOR-HINT".to_string(), "7".to_string())]);
+        assert_eq!(master.iframe_variants[0].uri, "v1080/iframes.m3u8");
+        assert!(playlist.to_string().contains("SUBTITLES=\"subs\",CLOSED-CAPTIONS=NONE\nv1080/prog.m3u8?token=xyz\n"));
+
+        // Relative to where the master was finally served from, not where it was requested
+        let mut resolved = playlist.clone();
+        resolved.resolve_uris(&Url::parse("https://cdn.example/hls/ep1/master.m3u8?sig=1").unwrap());
+        let Playlist::Master(master) = resolved else { unreachable!() };
+        assert_eq!(master.variants[0].uri, "https://cdn.example/hls/ep1/v1080/prog.m3u8?token=xyz");
+        assert_eq!(master.variants[1].uri, "https://cdn.example/hls/alt/v360/prog.m3u8");
+        assert_eq!(master.renditions[2].uri.as_deref(), Some("https://cdn.example/subs/en.m3u8?lang=en"));
+        assert_eq!(master.session_data[1].uri.as_deref(), Some("https://cdn.example/hls/meta/chapters.json"));
+        assert_eq!(master.session_keys[0].uri.as_deref(), Some("https://keys.example/k1?t=abc"));
+        assert_eq!(master.iframe_variants[0].uri, "https://cdn.example/hls/ep1/v1080/iframes.m3u8");
+    }
+
+    #[test]
+    fn test_media_fixture_keys_maps_and_byteranges() {
+        let playlist = Playlist::parse(MEDIA_FIXTURE).unwrap();
+        round_trips(&playlist);
+        let Playlist::Media(media) = &playlist else { panic!("not a media playlist") };
+        assert_eq!((media.target_duration, media.media_sequence, media.discontinuity_sequence), (Some(10), Some(120), Some(2)));
+        assert_eq!(media.playlist_type.as_deref(), Some("VOD"));
+        assert!(media.end_list);
+        assert_eq!(media.segments.len(), 4);
+        assert!((media.segments.iter().map(|s| s.duration).sum::<f64>() - 32.519).abs() < 1e-9);
+
+        let first = &media.segments[0];
+        assert_eq!(first.title, "Cold open");
+        assert_eq!(first.map.as_ref().and_then(|m| m.byte_range), Some(ByteRange { length: 720, offset: Some(0) }));
+        assert_eq!(first.keys[0].iv.as_deref(), Some("0x0000000000000000000000000000ABCD"));
+        assert_eq!(first.byte_range, Some(ByteRange { length: 150000, offset: Some(720) }));
+        assert_eq!(first.program_date_time.as_deref(), Some("2024-04-01T12:00:00.000Z"));
+        assert_eq!(media.segments[1].byte_range, Some(ByteRange { length: 162000, offset: None }));
+
+        let ad = &media.segments[2];
+        assert!(ad.discontinuity && media.segments[3].discontinuity);
+        assert_eq!((ad.keys[0].method.as_str(), ad.keys[0].uri.as_deref()), ("NONE", None));
+        assert!(ad.tags[0].starts_with("#EXT-X-DATERANGE:ID=\"ad-1\""));
+
+        let mut resolved = playlist.clone();
+        resolved.resolve_uris(&Url::parse("https://media.example/v/ep1/prog.m3u8?token=1").unwrap());
+        let Playlist::Media(media) = resolved else { unreachable!() };
+        assert_eq!(media.segments[0].uri, "https://media.example/v/ep1/main.mp4");
+        assert_eq!(media.segments[0].map.as_ref().unwrap().uri, "https://media.example/v/ep1/init.mp4");
+        assert_eq!(media.segments[0].keys[0].uri.as_deref(), Some("https://media.example/v/keys/key.bin?exp=99"));
+        assert_eq!(media.segments[2].uri, "https://ads.example/slot/a1.ts?c=1");
+        assert_eq!(media.segments[3].uri, "https://media.example/seg/part3.ts");
+    }
+
+    #[test]
+    fn test_live_fixture_keeps_ll_hls_tags() {
+        let mut playlist = Playlist::parse(LIVE_FIXTURE).unwrap();
+        round_trips(&playlist);
+        playlist.resolve_uris(&Url::parse("https://live.example/ch1/index.m3u8").unwrap());
+        let Playlist::Media(media) = &playlist else { panic!("not a media playlist") };
+        assert!(!media.end_list);
+        assert_eq!(media.tags, vec!["#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,PART-HOLD-BACK=1.0", "#EXT-X-PART-INF:PART-TARGET=0.333"]);
+        assert_eq!(media.segments[1].tags, vec!["#EXT-X-PART:DURATION=0.333,URI=\"https://live.example/ch1/filePart2641.0.ts\",INDEPENDENT=YES"]);
+        assert_eq!(media.trailing_tags, vec!["#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"https://live.example/ch1/filePart2642.0.ts\""]);
+        assert!(playlist.to_string().ends_with("fileSequence2641.ts\n#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"https://live.example/ch1/filePart2642.0.ts\"\n"));
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::Duration;
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER};
+use reqwest::{Client, RequestBuilder, Response};
+use crate::error::{NezukoError, Result};
+use crate::proxy_guard;
+use crate::ratelimit::{HostLimiter, RateLimit};
+
+pub const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/121.0.0.0 Safari/537.36";
+
+/// Never wait longer than this between two attempts, even if the server asks for it
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+static CLIENT: OnceLock<HttpClient> = OnceLock::new();
+
+/// Settings for the shared client. Change them with `init` before the first request.
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    /// Sent with every request unless the request sets the same header itself
+    pub default_headers: HashMap<String, String>,
+    /// Whole-request timeout for hosts not listed in `host_timeouts`
+    pub timeout: Duration,
+    /// Per-host overrides; "example.com" also matches its subdomains
+    pub host_timeouts: HashMap<String, Duration>,
+    pub connect_timeout: Duration,
+    /// Extra attempts after the first one (on network errors, 429 and 5xx)
+    pub max_retries: u32,
+    /// First retry waits this long, then it doubles
+    pub backoff_base: Duration,
+    /// Token bucket per scraped host (matched like `host_timeouts`); other hosts are unlimited
+    pub host_rate_limits: HashMap<String, RateLimit>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        let mut default_headers = HashMap::new();
+        default_headers.insert("User-Agent".to_string(), USER_AGENT.to_string());
+        default_headers.insert("Accept-Language".to_string(), "en-US,en;q=0.9".to_string());
+
+        let mut host_timeouts = HashMap::new();
+        host_timeouts.insert("api.allanime.day".to_string(), Duration::from_secs(10));
+        host_timeouts.insert("api.ani.zip".to_string(), Duration::from_secs(8));
+        host_timeouts.insert("hianime.bz".to_string(), Duration::from_secs(20));
+        host_timeouts.insert("megacloud.blog".to_string(), Duration::from_secs(20));
+        host_timeouts.insert("anitaku.to".to_string(), Duration::from_secs(20));
+
+        let mut host_rate_limits = HashMap::new();
+        host_rate_limits.insert("api.allanime.day".to_string(), RateLimit { burst: 6, per_second: 3.0 });
+        host_rate_limits.insert("hianime.bz".to_string(), RateLimit { burst: 4, per_second: 2.0 });
+        host_rate_limits.insert("megacloud.blog".to_string(), RateLimit { burst: 4, per_second: 2.0 });
+        host_rate_limits.insert("anitaku.to".to_string(), RateLimit { burst: 4, per_second: 2.0 });
+        host_rate_limits.insert("api.ani.zip".to_string(), RateLimit { burst: 5, per_second: 5.0 });
+
+        Self {
+            default_headers,
+            timeout: Duration::from_secs(15),
+            host_timeouts,
+            connect_timeout: Duration::from_secs(8),
+            max_retries: 2,
+            backoff_base: Duration::from_millis(400),
+            host_rate_limits,
+        }
+    }
+}
+
+impl HttpConfig {
+    /// Defaults, plus overrides from the environment:
+    /// `NEZUKO_USER_AGENT` and `NEZUKO_HTTP_HEADERS` (JSON object of extra headers).
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Ok(headers_json) = std::env::var("NEZUKO_HTTP_HEADERS") {
+            match serde_json::from_str::<HashMap<String, String>>(&headers_json) {
+                Ok(headers) => config.default_headers.extend(headers),
+                Err(e) => println!("DEBUG: Ignoring NEZUKO_HTTP_HEADERS: {}", e),
+            }
+        }
+        if let Ok(ua) = std::env::var("NEZUKO_USER_AGENT") {
+            config.default_headers.insert("User-Agent".to_string(), ua);
+        }
+        config
+    }
+}
+
+/// Pooled reqwest client shared by every extractor and the proxy
+pub struct HttpClient {
+    inner: Client,
+    /// Same settings, checked against SSRF (see `proxy_guard::guard`) for proxied targets
+    guarded: Client,
+    config: HttpConfig,
+    limiter: HostLimiter,
+}
+
+impl HttpClient {
+    pub fn new(config: HttpConfig) -> Self {
+        Self::with_guard(config, proxy_guard::refused)
+    }
+
+    /// `new`, with `refuse` deciding which addresses proxied targets may not reach
+    pub fn with_guard(config: HttpConfig, refuse: fn(std::net::IpAddr) -> bool) -> Self {
+        let mut headers = HeaderMap::new();
+        for (key, value) in &config.default_headers {
+            match (HeaderName::from_bytes(key.as_bytes()), HeaderValue::from_str(value)) {
+                (Ok(k), Ok(v)) => { headers.insert(k, v); },
+                _ => println!("DEBUG: Ignoring invalid default header '{}'", key),
+            }
+        }
+
+        // No client-wide timeout: streamed proxy bodies can legitimately take minutes.
+        // Scraper requests get their per-host timeout in `get`.
+        let builder = || Client::builder()
+            .default_headers(headers.clone())
+            .connect_timeout(config.connect_timeout)
+            .pool_idle_timeout(Duration::from_secs(90))
+            .pool_max_idle_per_host(8);
+        let inner = builder().build().expect("Failed to build HTTP client");
+        let guarded = proxy_guard::guard(builder(), refuse).build().expect("Failed to build HTTP client");
+
+        let limiter = HostLimiter::new(config.host_rate_limits.clone());
+        Self { inner, guarded, config, limiter }
+    }
+
+    /// Timeout for a URL, using the most specific `host_timeouts` entry
+    pub fn timeout_for(&self, url: &str) -> Duration {
+        let host = match reqwest::Url::parse(url) {
+            Ok(u) => u.host_str().unwrap_or("").to_string(),
+            Err(_) => return self.config.timeout,
+        };
+
+        match_host(&self.config.host_timeouts, &host)
+            .map(|(_, t)| *t)
+            .unwrap_or(self.config.timeout)
+    }
+
+    /// GET with the host's timeout applied
+    pub fn get(&self, url: &str) -> RequestBuilder {
+        self.inner.get(url).timeout(self.timeout_for(url))
+    }
+
+    /// GET without a whole-request timeout, for bodies that are streamed to the player.
+    /// Proxied targets only: redirects and DNS answers are SSRF-checked.
+    pub fn get_streaming(&self, url: &str) -> RequestBuilder {
+        self.guarded.get(url)
+    }
+
+    /// HEAD with the host's timeout applied, SSRF-checked like `get_streaming`
+    pub fn head(&self, url: &str) -> RequestBuilder {
+        self.guarded.head(url).timeout(self.timeout_for(url))
+    }
+
+    /// Send a request, waiting for the host's rate limit and retrying network errors,
+    /// 429 and 5xx with exponential backoff.
+    /// The final response is returned as-is; callers still run `NezukoError::check_status`.
+    pub async fn send(&self, provider: &str, request: RequestBuilder) -> Result<Response> {
+        let mut attempt = 0;
+        loop {
+            let Some(this_try) = request.try_clone() else {
+                // Streaming bodies cannot be replayed
+                return request.send().await.map_err(|e| NezukoError::from_reqwest(provider, e));
+            };
+            // Run on the client the request was built from (it may be the guarded one)
+            let (client, this_try) = this_try.build_split();
+            let this_try = this_try.map_err(|e| NezukoError::from_reqwest(provider, e))?;
+
+            self.limiter.acquire(this_try.url().host_str().unwrap_or("")).await;
+
+            let retry_after = match client.execute(this_try).await {
+                Ok(res) if attempt < self.config.max_retries && is_retryable_status(res.status().as_u16()) => {
+                    println!("DEBUG: {} {} returned {}, retrying", provider, res.url(), res.status());
+                    parse_retry_after(&res)
+                },
+                Ok(res) => return Ok(res),
+                Err(e) => {
+                    let err = NezukoError::from_reqwest(provider, e);
+                    if attempt >= self.config.max_retries || !err.is_retryable() {
+                        return Err(err);
+                    }
+                    println!("DEBUG: {}, retrying", err);
+                    None
+                },
+            };
+
+            tokio::time::sleep(self.backoff(attempt, retry_after)).await;
+            attempt += 1;
+        }
+    }
+
+    fn backoff(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        let exponential = self.config.backoff_base.saturating_mul(2u32.saturating_pow(attempt));
+        retry_after.unwrap_or(exponential).min(MAX_BACKOFF)
+    }
+}
+
+/// Most specific entry for `host`: an exact key, or the longest key it is a subdomain of
+pub(crate) fn match_host<'a, V>(map: &'a HashMap<String, V>, host: &str) -> Option<(&'a String, &'a V)> {
+    map.iter()
+        .filter(|(h, _)| host == h.as_str() || host.ends_with(&format!(".{}", h)))
+        .max_by_key(|(h, _)| h.len())
+}
+
+fn is_retryable_status(status: u16) -> bool {
+    status == 429 || (500..600).contains(&status)
+}
+
+/// Only the delay-seconds form of Retry-After is honoured
+fn parse_retry_after(res: &Response) -> Option<Duration> {
+    res.headers().get(RETRY_AFTER)?
+        .to_str().ok()?
+        .trim().parse::<u64>().ok()
+        .map(Duration::from_secs)
+}
+
+/// `.send_with_retry(provider)` on any request built from the shared client
+#[async_trait]
+pub trait RequestExt {
+    async fn send_with_retry(self, provider: &str) -> Result<Response>;
+}
+
+#[async_trait]
+impl RequestExt for RequestBuilder {
+    async fn send_with_retry(self, provider: &str) -> Result<Response> {
+        client().send(provider, self).await
+    }
+}
+
+/// Install a custom configuration. Returns false if the client was already created.
+pub fn init(config: HttpConfig) -> bool {
+    CLIENT.set(HttpClient::new(config)).is_ok()
+}
+
+/// The shared client (created with `HttpConfig::default()` on first use)
+pub fn client() -> &'static HttpClient {
+    CLIENT.get_or_init(|| HttpClient::new(HttpConfig::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_timeouts() {
+        let mut config = HttpConfig::default();
+        config.host_timeouts.insert("cdn.example.com".to_string(), Duration::from_secs(3));
+        config.host_timeouts.insert("example.com".to_string(), Duration::from_secs(7));
+        let client = HttpClient::new(config);
+
+        assert_eq!(client.timeout_for("https://api.allanime.day/api?x=1"), Duration::from_secs(10));
+        assert_eq!(client.timeout_for("https://www.example.com/a"), Duration::from_secs(7));
+        assert_eq!(client.timeout_for("https://edge.cdn.example.com/a"), Duration::from_secs(3));
+        assert_eq!(client.timeout_for("https://notexample.com/a"), Duration::from_secs(15));
+        assert_eq!(client.timeout_for("not a url"), Duration::from_secs(15));
+    }
+
+    #[test]
+    fn test_backoff_doubles_and_caps() {
+        let client = HttpClient::new(HttpConfig::default());
+        assert_eq!(client.backoff(0, None), Duration::from_millis(400));
+        assert_eq!(client.backoff(2, None), Duration::from_millis(1600));
+        assert_eq!(client.backoff(0, Some(Duration::from_secs(3))), Duration::from_secs(3));
+        assert_eq!(client.backoff(30, None), MAX_BACKOFF);
+        assert!(is_retryable_status(503) && is_retryable_status(429) && !is_retryable_status(404));
+    }
+}
@@ -0,0 +1,406 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+// ============================================================
+// Typed anime / episode identifiers
+// ------------------------------------------------------------
+// Canonical encoding (version 1):
+//   MediaId   v1:allanime:<show_id>:<sub|dub|raw>
+//             v1:hianime:<slug>:<sub|dub|raw>[:<dubbed episode count>]
+//             v1:anitaku:<slug>
+//   EpisodeId v1:allanime:<show_id>:<sub|dub|raw>:<episode_string>
+//             v1:hianime:<slug>:<sub|dub|raw>:<episode_id>
+//             v1:anitaku:<episode_slug>
+//
+// The parser also accepts the pre-v1 strings that are still stored in
+// watch history / libraries ("allanime:id:dub", "allanime:id-dub",
+// "allanime:id|ep|mode", "hianime:id|ep_id", bare Anitaku slugs) and
+// migrates them to the typed form, as is "v1:hianime:<slug>" from before
+// HiAnime show IDs carried a category (read as sub). Serialisation
+// always emits the current v1 form.
+// ============================================================
+
+const VERSION_PREFIX: &str = "v1:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TranslationType {
+    Sub,
+    Dub,
+    /// Untranslated (AllAnime only)
+    Raw,
+}
+
+impl TranslationType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TranslationType::Sub => "sub",
+            TranslationType::Dub => "dub",
+            TranslationType::Raw => "raw",
+        }
+    }
+}
+
+impl FromStr for TranslationType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sub" => Ok(TranslationType::Sub),
+            "dub" => Ok(TranslationType::Dub),
+            "raw" => Ok(TranslationType::Raw),
+            other => Err(format!("Unknown translation type '{}'", other)),
+        }
+    }
+}
+
+impl fmt::Display for TranslationType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Identifies a show on a specific provider
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MediaId {
+    AllAnime { show_id: String, mode: TranslationType },
+    /// `dub_episodes`: how many episodes the dub covers, when the search card said so
+    HiAnime { slug: String, category: TranslationType, dub_episodes: Option<u32> },
+    Anitaku { slug: String },
+}
+
+/// Identifies a single playable episode on a specific provider
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum EpisodeId {
+    AllAnime { show_id: String, mode: TranslationType, episode: String },
+    HiAnime { slug: String, category: TranslationType, episode_id: String },
+    Anitaku { slug: String },
+}
+
+impl MediaId {
+    /// Short provider ID ("allanime", "hianime", "anitaku") used for registry lookup
+    pub fn provider(&self) -> &'static str {
+        match self {
+            MediaId::AllAnime { .. } => "allanime",
+            MediaId::HiAnime { .. } => "hianime",
+            MediaId::Anitaku { .. } => "anitaku",
+        }
+    }
+}
+
+impl EpisodeId {
+    pub fn provider(&self) -> &'static str {
+        match self {
+            EpisodeId::AllAnime { .. } => "allanime",
+            EpisodeId::HiAnime { .. } => "hianime",
+            EpisodeId::Anitaku { .. } => "anitaku",
+        }
+    }
+}
+
+// ------------------------------------------------------------
+// Canonical encoding
+// ------------------------------------------------------------
+
+impl fmt::Display for MediaId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MediaId::AllAnime { show_id, mode } => write!(f, "{}allanime:{}:{}", VERSION_PREFIX, show_id, mode),
+            MediaId::HiAnime { slug, category, dub_episodes: None } => write!(f, "{}hianime:{}:{}", VERSION_PREFIX, slug, category),
+            MediaId::HiAnime { slug, category, dub_episodes: Some(count) } => write!(f, "{}hianime:{}:{}:{}", VERSION_PREFIX, slug, category, count),
+            MediaId::Anitaku { slug } => write!(f, "{}anitaku:{}", VERSION_PREFIX, slug),
+        }
+    }
+}
+
+impl fmt::Display for EpisodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EpisodeId::AllAnime { show_id, mode, episode } => write!(f, "{}allanime:{}:{}:{}", VERSION_PREFIX, show_id, mode, episode),
+            EpisodeId::HiAnime { slug, category, episode_id } => write!(f, "{}hianime:{}:{}:{}", VERSION_PREFIX, slug, category, episode_id),
+            EpisodeId::Anitaku { slug } => write!(f, "{}anitaku:{}", VERSION_PREFIX, slug),
+        }
+    }
+}
+
+impl FromStr for MediaId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err("Empty media ID".to_string());
+        }
+
+        if let Some(rest) = s.strip_prefix(VERSION_PREFIX) {
+            let parts: Vec<&str> = rest.split(':').collect();
+            return match parts.as_slice() {
+                ["allanime", show_id, mode] if !show_id.is_empty() => Ok(MediaId::AllAnime {
+                    show_id: show_id.to_string(),
+                    mode: mode.parse()?,
+                }),
+                ["hianime", slug] if !slug.is_empty() => Ok(MediaId::HiAnime { slug: slug.to_string(), category: TranslationType::Sub, dub_episodes: None }),
+                ["hianime", slug, category] if !slug.is_empty() => Ok(MediaId::HiAnime {
+                    slug: slug.to_string(),
+                    category: category.parse()?,
+                    dub_episodes: None,
+                }),
+                ["hianime", slug, category, count] if !slug.is_empty() => Ok(MediaId::HiAnime {
+                    slug: slug.to_string(),
+                    category: category.parse()?,
+                    dub_episodes: Some(count.parse().map_err(|_| format!("Invalid dub episode count in '{}'", s))?),
+                }),
+                ["anitaku", slug] if !slug.is_empty() => Ok(MediaId::Anitaku { slug: slug.to_string() }),
+                _ => Err(format!("Invalid media ID '{}'", s)),
+            };
+        }
+
+        parse_legacy_media(s)
+    }
+}
+
+impl FromStr for EpisodeId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err("Empty episode ID".to_string());
+        }
+
+        if let Some(rest) = s.strip_prefix(VERSION_PREFIX) {
+            // Episode strings/IDs are always the last field, so cap the split there
+            let parts: Vec<&str> = rest.splitn(4, ':').collect();
+            return match parts.as_slice() {
+                ["allanime", show_id, mode, episode] if !show_id.is_empty() && !episode.is_empty() => Ok(EpisodeId::AllAnime {
+                    show_id: show_id.to_string(),
+                    mode: mode.parse()?,
+                    episode: episode.to_string(),
+                }),
+                ["hianime", slug, category, episode_id] if !slug.is_empty() && !episode_id.is_empty() => Ok(EpisodeId::HiAnime {
+                    slug: slug.to_string(),
+                    category: category.parse()?,
+                    episode_id: episode_id.to_string(),
+                }),
+                ["anitaku", slug] if !slug.is_empty() => Ok(EpisodeId::Anitaku { slug: slug.to_string() }),
+                _ => Err(format!("Invalid episode ID '{}'", s)),
+            };
+        }
+
+        parse_legacy_episode(s)
+    }
+}
+
+// ------------------------------------------------------------
+// Legacy migration
+// ------------------------------------------------------------
+
+/// "allanime:id", "allanime:id:sub", "allanime:id:dub", "allanime:id:raw", "allanime:id-dub",
+/// "hianime:slug", or a bare Anitaku slug.
+fn parse_legacy_media(s: &str) -> Result<MediaId, String> {
+    if let Some(rest) = s.strip_prefix("allanime:") {
+        let (show_id, mode) = if let Some(id) = rest.strip_suffix(":dub") {
+            (id, TranslationType::Dub)
+        } else if let Some(id) = rest.strip_suffix(":sub") {
+            (id, TranslationType::Sub)
+        } else if let Some(id) = rest.strip_suffix(":raw") {
+            (id, TranslationType::Raw)
+        } else if let Some(id) = rest.strip_suffix("-dub") {
+            (id, TranslationType::Dub)
+        } else {
+            (rest, TranslationType::Sub)
+        };
+        if show_id.is_empty() {
+            return Err(format!("Invalid AllAnime ID '{}'", s));
+        }
+        return Ok(MediaId::AllAnime { show_id: show_id.to_string(), mode });
+    }
+
+    if let Some(slug) = s.strip_prefix("hianime:") {
+        if slug.is_empty() {
+            return Err(format!("Invalid HiAnime ID '{}'", s));
+        }
+        return Ok(MediaId::HiAnime { slug: slug.to_string(), category: TranslationType::Sub, dub_episodes: None });
+    }
+
+    if s.contains(':') || s.contains('|') {
+        return Err(format!("Unrecognised media ID '{}'", s));
+    }
+
+    Ok(MediaId::Anitaku { slug: s.to_string() })
+}
+
+/// "allanime:id|ep|mode", "hianime:slug|ep_id[|category]", or a bare Anitaku episode slug.
+fn parse_legacy_episode(s: &str) -> Result<EpisodeId, String> {
+    if s.starts_with("allanime:") {
+        let parts: Vec<&str> = s.split('|').collect();
+        if parts.len() < 3 {
+            return Err(format!("Invalid AllAnime episode ID '{}'", s));
+        }
+        let show_id = parts[0].trim_start_matches("allanime:");
+        if show_id.is_empty() || parts[1].is_empty() {
+            return Err(format!("Invalid AllAnime episode ID '{}'", s));
+        }
+        return Ok(EpisodeId::AllAnime {
+            show_id: show_id.to_string(),
+            mode: parts[2].parse()?,
+            episode: parts[1].to_string(),
+        });
+    }
+
+    if s.starts_with("hianime:") {
+        let parts: Vec<&str> = s.split('|').collect();
+        if parts.len() < 2 || parts[1].is_empty() {
+            return Err(format!("Invalid HiAnime episode ID '{}'", s));
+        }
+        let category = match parts.get(2) {
+            Some(c) => c.parse()?,
+            None => TranslationType::Sub, // Legacy IDs had no category
+        };
+        return Ok(EpisodeId::HiAnime {
+            slug: parts[0].trim_start_matches("hianime:").to_string(),
+            category,
+            episode_id: parts[1].to_string(),
+        });
+    }
+
+    if s.contains(':') || s.contains('|') {
+        return Err(format!("Unrecognised episode ID '{}'", s));
+    }
+
+    Ok(EpisodeId::Anitaku { slug: s.to_string() })
+}
+
+// ------------------------------------------------------------
+// Serde (as canonical strings, so the JSON bridge stays string-typed)
+// ------------------------------------------------------------
+
+impl Serialize for TranslationType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TranslationType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for MediaId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for MediaId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for EpisodeId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for EpisodeId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_media_id_round_trip() {
+        let ids = vec![
+            MediaId::AllAnime { show_id: "ReooPAxPMsHM4KPMY".to_string(), mode: TranslationType::Dub },
+            MediaId::AllAnime { show_id: "ReooPAxPMsHM4KPMY".to_string(), mode: TranslationType::Raw },
+            MediaId::HiAnime { slug: "one-piece-100".to_string(), category: TranslationType::Sub, dub_episodes: None },
+            MediaId::HiAnime { slug: "one-piece-100".to_string(), category: TranslationType::Dub, dub_episodes: None },
+            MediaId::HiAnime { slug: "one-piece-100".to_string(), category: TranslationType::Dub, dub_episodes: Some(1088) },
+            MediaId::Anitaku { slug: "one-piece".to_string() },
+        ];
+        for id in ids {
+            let encoded = id.to_string();
+            assert!(encoded.starts_with("v1:"));
+            assert_eq!(encoded.parse::<MediaId>().unwrap(), id);
+
+            let json = serde_json::to_string(&id).unwrap();
+            assert_eq!(serde_json::from_str::<MediaId>(&json).unwrap(), id);
+        }
+    }
+
+    #[test]
+    fn test_episode_id_round_trip() {
+        let ids = vec![
+            EpisodeId::AllAnime { show_id: "ReooPAxPMsHM4KPMY".to_string(), mode: TranslationType::Sub, episode: "1000".to_string() },
+            EpisodeId::AllAnime { show_id: "gvwLtiYciaenJRoFy".to_string(), mode: TranslationType::Dub, episode: "12.5".to_string() },
+            EpisodeId::HiAnime { slug: "one-piece-100".to_string(), category: TranslationType::Dub, episode_id: "2142".to_string() },
+            EpisodeId::Anitaku { slug: "one-piece-episode-1".to_string() },
+        ];
+        for id in ids {
+            let encoded = id.to_string();
+            assert_eq!(encoded.parse::<EpisodeId>().unwrap(), id);
+
+            let json = serde_json::to_string(&id).unwrap();
+            assert_eq!(serde_json::from_str::<EpisodeId>(&json).unwrap(), id);
+        }
+    }
+
+    #[test]
+    fn test_legacy_media_ids_migrate() {
+        let show = |mode| MediaId::AllAnime { show_id: "abc123".to_string(), mode };
+        assert_eq!("allanime:abc123".parse::<MediaId>().unwrap(), show(TranslationType::Sub));
+        assert_eq!("allanime:abc123:sub".parse::<MediaId>().unwrap(), show(TranslationType::Sub));
+        assert_eq!("allanime:abc123:raw".parse::<MediaId>().unwrap(), show(TranslationType::Raw));
+        assert_eq!("allanime:abc123:dub".parse::<MediaId>().unwrap(), show(TranslationType::Dub));
+        assert_eq!("allanime:abc123-dub".parse::<MediaId>().unwrap(), show(TranslationType::Dub));
+        let one_piece = |category| MediaId::HiAnime { slug: "one-piece-100".to_string(), category, dub_episodes: None };
+        assert_eq!("hianime:one-piece-100".parse::<MediaId>().unwrap(), one_piece(TranslationType::Sub));
+        assert_eq!("v1:hianime:one-piece-100".parse::<MediaId>().unwrap(), one_piece(TranslationType::Sub));
+        assert_eq!("v1:hianime:one-piece-100:dub".parse::<MediaId>().unwrap(), one_piece(TranslationType::Dub));
+        assert_eq!("one-piece-dub".parse::<MediaId>().unwrap(), MediaId::Anitaku { slug: "one-piece-dub".to_string() });
+
+        // Migrated IDs re-encode as v1
+        assert_eq!("allanime:abc123-dub".parse::<MediaId>().unwrap().to_string(), "v1:allanime:abc123:dub");
+    }
+
+    #[test]
+    fn test_legacy_episode_ids_migrate() {
+        assert_eq!(
+            "allanime:abc123|5|dub".parse::<EpisodeId>().unwrap(),
+            EpisodeId::AllAnime { show_id: "abc123".to_string(), mode: TranslationType::Dub, episode: "5".to_string() }
+        );
+        assert_eq!(
+            "hianime:one-piece-100|2142".parse::<EpisodeId>().unwrap(),
+            EpisodeId::HiAnime { slug: "one-piece-100".to_string(), category: TranslationType::Sub, episode_id: "2142".to_string() }
+        );
+        assert_eq!(
+            "hianime:one-piece-100|2142|dub".parse::<EpisodeId>().unwrap(),
+            EpisodeId::HiAnime { slug: "one-piece-100".to_string(), category: TranslationType::Dub, episode_id: "2142".to_string() }
+        );
+        assert_eq!(
+            "one-piece-episode-1".parse::<EpisodeId>().unwrap(),
+            EpisodeId::Anitaku { slug: "one-piece-episode-1".to_string() }
+        );
+    }
+
+    #[test]
+    fn test_invalid_ids_rejected() {
+        assert!("".parse::<MediaId>().is_err());
+        assert!("v1:allanime:abc".parse::<MediaId>().is_err());
+        assert!("v1:allanime:abc:raw-ish".parse::<MediaId>().is_err());
+        assert!("v1:unknown:abc".parse::<MediaId>().is_err());
+        assert!("allanime:abc|5".parse::<EpisodeId>().is_err());
+        assert!("hianime:slug".parse::<EpisodeId>().is_err());
+        assert!("zoro:slug".parse::<MediaId>().is_err());
+    }
+}
@@ -1,8 +1,25 @@
-use tauri::Manager;
+use std::sync::Arc;
+use tauri::{Manager, State};
 
+mod adfilter;
 mod anime;
+mod cache;
+mod chapters;
+mod error;
+mod fallback;
+mod health;
+mod hls;
+mod http;
 pub mod extractors;
+mod ids;
+mod merge;
+mod provider;
+mod ranking;
 mod proxy;
+mod proxy_guard;
+mod ratelimit;
+mod segment_cache;
+mod singleflight;
 #[cfg(test)]
 mod allanime_test;
 #[cfg(test)]
@@ -13,8 +30,16 @@ mod zephyr_test;
 mod provider_test;
 
 
-use anime::{AnimeResult, Episode, VideoSource};
+use anime::{AnimeResult, Episode, SearchPage, VideoSource};
+use cache::{CacheStats, DiskCache};
+use error::NezukoError;
+use health::HealthReport;
+use extractors::allanime::SearchOptions;
+use extractors::hianime::{HiAnimeFilters, HiAnimeServer};
 use extractors::mapping::ProviderMappings;
+use fallback::ResolvedStream;
+use ids::{EpisodeId, MediaId, TranslationType};
+use provider::{ProviderInfo, ProviderRegistry};
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 #[tauri::command]
@@ -23,62 +48,199 @@ fn greet(name: &str) -> String {
 }
 
 #[tauri::command]
-async fn search_anime_command(query: String) -> Result<Vec<AnimeResult>, String> {
-    anime::search_anime(&query).await
+fn list_providers_command(registry: State<'_, ProviderRegistry>) -> Vec<ProviderInfo> {
+    registry.infos()
 }
 
 #[tauri::command]
-async fn search_anitaku_command(query: String) -> Result<Vec<AnimeResult>, String> {
-    anime::search_anitaku(&query).await
+fn get_provider_health(registry: State<'_, ProviderRegistry>) -> Vec<HealthReport> {
+    registry.health()
 }
 
 #[tauri::command]
-async fn search_awi_command(query: String) -> Result<Vec<AnimeResult>, String> {
-    anime::search_awi(&query).await
+async fn search_anime_command(registry: State<'_, ProviderRegistry>, query: String) -> Result<Vec<AnimeResult>, NezukoError> {
+    let results = registry.search(&query).await?;
+    Ok(ranking::rank(&query, results))
 }
 
+/// Search results grouped per show across providers, best match first. With `anilist_id`, results AniZip
+/// maps to that show are grouped together even when their titles differ.
 #[tauri::command]
-async fn check_anitaku_exists_command(id: String) -> Result<bool, String> {
-    anime::check_anitaku_exists(&id).await
+async fn search_anime_merged_command(
+    registry: State<'_, ProviderRegistry>,
+    cache: State<'_, Arc<DiskCache>>,
+    query: String,
+    anilist_id: Option<i32>,
+) -> Result<Vec<AnimeResult>, NezukoError> {
+    let results = registry.search(&query).await?;
+    let anchors = match anilist_id {
+        Some(id) => match cached_mappings(&cache, id).await {
+            Ok(mappings) => registry.mapped_ids(&mappings),
+            Err(e) => {
+                println!("DEBUG: No mappings for AniList {}, merging by title only: {}", id, e);
+                Vec::new()
+            },
+        },
+        None => Vec::new(),
+    };
+    Ok(ranking::rank(&query, merge::merge_results(results, &anchors)))
 }
 
+/// Paginated, filtered AllAnime catalog search (an empty query browses)
 #[tauri::command]
-async fn get_episodes_command(id: String) -> Result<Vec<Episode>, String> {
-    anime::get_episodes(&id).await
+async fn search_allanime_command(
+    registry: State<'_, ProviderRegistry>,
+    query: String,
+    options: Option<SearchOptions>,
+) -> Result<SearchPage, NezukoError> {
+    let options = options.unwrap_or_default();
+    let request = format!("{}:{}", query.trim().to_lowercase(), serde_json::to_string(&options).unwrap_or_default());
+    let key = DiskCache::key(cache::SEARCH, "allanime", &request);
+    registry.call_cached("allanime", &key, cache::SEARCH_TTL, extractors::allanime::search_page(&query, &options)).await
 }
 
+/// Paginated HiAnime catalog search; `filters` has the same shape as the catalog `FilterPanel`
 #[tauri::command]
-async fn get_anitaku_episodes_command(id: String) -> Result<Vec<Episode>, String> {
-    anime::get_anitaku_episodes(&id).await
+async fn search_hianime_command(
+    registry: State<'_, ProviderRegistry>,
+    filters: Option<HiAnimeFilters>,
+    page: Option<u32>,
+) -> Result<SearchPage, NezukoError> {
+    let filters = filters.unwrap_or_default();
+    let page = page.unwrap_or(1);
+    let request = format!("{}:{}", page, serde_json::to_string(&filters).unwrap_or_default().to_lowercase());
+    let key = DiskCache::key(cache::SEARCH, "hianime", &request);
+    registry.call_cached("hianime", &key, cache::SEARCH_TTL, extractors::hianime::search_results_page(&filters, page)).await
 }
 
 #[tauri::command]
-async fn get_awi_episodes_command(id: String) -> Result<Vec<Episode>, String> {
-    anime::get_awi_episodes(&id).await
+async fn search_provider_command(registry: State<'_, ProviderRegistry>, provider: String, query: String) -> Result<Vec<AnimeResult>, NezukoError> {
+    registry.search_provider(&provider, &query).await
 }
 
+/// With `anilist_id`, episodes are completed with AniZip titles, synopses, thumbnails and air dates
 #[tauri::command]
-async fn get_stream_command(id: String) -> Result<VideoSource, String> {
-    anime::get_stream_source(&id).await
+async fn get_episodes_command(
+    registry: State<'_, ProviderRegistry>,
+    cache: State<'_, Arc<DiskCache>>,
+    id: MediaId,
+    anilist_id: Option<i32>,
+) -> Result<Vec<Episode>, NezukoError> {
+    let mut episodes = registry.episodes(&id).await?;
+    if let Some(anilist_id) = anilist_id {
+        // Metadata is optional: a failed AniZip lookup still returns the episodes
+        match cached_mappings(&cache, anilist_id).await {
+            Ok(mappings) => mappings.enrich_episodes(&mut episodes),
+            Err(e) => println!("DEBUG: No AniZip episode metadata for {}: {}", anilist_id, e),
+        }
+    }
+    Ok(episodes)
 }
 
+/// `server_id` (from `get_hianime_servers`) bypasses the automatic server choice.
+/// HLS sources come back with their variants listed; `quality` ("1080p", "720", "best",
+/// "lowest", or a bandwidth label like "800k") pins the URL to one of them.
 #[tauri::command]
-async fn get_anitaku_stream_command(id: String) -> Result<VideoSource, String> {
-    anime::get_anitaku_stream(&id).await
+async fn get_stream_command(
+    registry: State<'_, ProviderRegistry>,
+    id: EpisodeId,
+    server_id: Option<String>,
+    quality: Option<String>,
+) -> Result<VideoSource, NezukoError> {
+    let source = match server_id {
+        None => registry.stream(&id).await?,
+        Some(server_id) => registry.call(id.provider(), extractors::hianime::stream_from_server(&id, Some(&server_id))).await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| NezukoError::not_found(id.provider(), format!("No sources on server {}", server_id)))?,
+    };
+
+    let preference = quality.as_deref().and_then(hls::QualityPreference::parse);
+    // Variant listing is best effort: the master URL still plays without it
+    match hls::inspect_source(source.clone(), preference).await {
+        Ok(inspected) => Ok(inspected),
+        Err(e) => {
+            println!("DEBUG: Could not inspect HLS master for {}: {}", id, e);
+            Ok(source)
+        },
+    }
+}
+
+/// Every HiAnime server for an episode (name, sub/dub/raw type and ID)
+#[tauri::command]
+async fn get_hianime_servers(registry: State<'_, ProviderRegistry>, episode_id: EpisodeId) -> Result<Vec<HiAnimeServer>, NezukoError> {
+    let EpisodeId::HiAnime { episode_id: ep_id, .. } = &episode_id else {
+        return Err(NezukoError::unsupported("hianime", format!("Not a HiAnime episode ID: {}", episode_id)));
+    };
+    let key = DiskCache::key(cache::SERVERS, "hianime", ep_id);
+    registry.call_cached("hianime", &key, cache::SERVERS_TTL, extractors::hianime::get_servers(ep_id)).await
+}
+
+/// All sources for an episode, best first, each with server name, type and resolution status
+#[tauri::command]
+async fn get_stream_candidates(registry: State<'_, ProviderRegistry>, id: EpisodeId) -> Result<Vec<VideoSource>, NezukoError> {
+    registry.stream_candidates(&id).await
 }
 
+/// First playable stream for an AniList episode, trying providers in priority order
 #[tauri::command]
-async fn get_awi_stream_command(id: String) -> Result<VideoSource, String> {
-    anime::get_awi_stream(&id).await
+async fn get_stream_for_anilist(
+    registry: State<'_, ProviderRegistry>,
+    cache: State<'_, Arc<DiskCache>>,
+    anilist_id: i32,
+    episode: f32,
+    language: TranslationType,
+) -> Result<ResolvedStream, NezukoError> {
+    let mappings = cached_mappings(&cache, anilist_id).await?;
+    fallback::stream_for_anilist(&registry, &mappings, episode, language).await
 }
 
 #[tauri::command]
-async fn get_mappings_command(anilist_id: i32) -> Result<ProviderMappings, String> {
-    extractors::mapping::get_mappings(anilist_id).await
+async fn get_mappings_command(cache: State<'_, Arc<DiskCache>>, anilist_id: i32) -> Result<ProviderMappings, NezukoError> {
+    cached_mappings(&cache, anilist_id).await
+}
+
+async fn cached_mappings(cache: &DiskCache, anilist_id: i32) -> Result<ProviderMappings, NezukoError> {
+    let key = DiskCache::key(cache::MAPPINGS, "anizip", &anilist_id.to_string());
+    if let Some(hit) = cache.get(&key) {
+        return Ok(hit);
+    }
+    let mappings = extractors::mapping::get_mappings(anilist_id).await?;
+    cache.put(&key, &mappings, cache::MAPPINGS_TTL);
+    Ok(mappings)
+}
+
+#[tauri::command]
+fn get_cache_stats_command(cache: State<'_, Arc<DiskCache>>) -> CacheStats {
+    cache.stats()
+}
+
+#[tauri::command]
+fn get_segment_cache_stats() -> segment_cache::SegmentCacheStats {
+    segment_cache::cache().stats()
+}
+
+/// `kind` is one of `cache::KINDS` ("search", "episodes", "stream", "candidates", "servers",
+/// "mappings") or "segments" (the proxy's HLS segment cache); omit it to clear everything
+#[tauri::command]
+fn clear_cache_command(cache: State<'_, Arc<DiskCache>>, kind: Option<String>) -> usize {
+    let removed = match kind.as_deref() {
+        Some("segments") => segment_cache::cache().clear(),
+        None => cache.clear(None) + segment_cache::cache().clear(),
+        Some(kind) if cache::KINDS.contains(&kind) => cache.clear(Some(kind)),
+        Some(kind) => {
+            println!("DEBUG: Unknown cache kind '{}' (expected one of {:?} or \"segments\")", kind, cache::KINDS);
+            0
+        },
+    };
+    println!("DEBUG: Cleared {} cache entries", removed);
+    removed
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    http::init(http::HttpConfig::from_env());
+
     // Start the proxy server in a separate async task
     tauri::async_runtime::spawn(async move {
         proxy::start_proxy_server().await;
@@ -87,19 +249,32 @@ pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_http::init())
+        .setup(|app| {
+            let cache_dir = app.path().app_data_dir()?.join("cache");
+            segment_cache::init(segment_cache::SegmentCacheConfig::from_env(), Some(cache_dir.join("segments")));
+            let cache = Arc::new(DiskCache::new(cache_dir));
+            app.manage(ProviderRegistry::default().with_cache(cache.clone()));
+            app.manage(cache);
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             greet, 
+            list_providers_command,
+            get_provider_health,
             search_anime_command,
-            search_anitaku_command,
-            check_anitaku_exists_command,
-            search_awi_command,
+            search_anime_merged_command,
+            search_allanime_command,
+            search_hianime_command,
+            search_provider_command,
             get_episodes_command,
-            get_anitaku_episodes_command,
-            get_awi_episodes_command,
             get_stream_command,
-            get_anitaku_stream_command,
-            get_awi_stream_command,
-            get_mappings_command
+            get_stream_candidates,
+            get_hianime_servers,
+            get_stream_for_anilist,
+            get_mappings_command,
+            get_cache_stats_command,
+            get_segment_cache_stats,
+            clear_cache_command
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
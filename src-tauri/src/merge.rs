@@ -0,0 +1,261 @@
+use std::collections::HashSet;
+use crate::anime::{AnimeResult, ProviderMatch};
+use crate::ids::MediaId;
+
+// ============================================================
+// Cross-provider search merging
+// ------------------------------------------------------------
+// The same show comes back once per provider (and twice from
+// Anitaku: "<slug>" and "<slug>-dub"). Results are grouped by
+// normalised title similarity nudged by episode counts; results
+// whose IDs AniZip mapped to the requested AniList entry are
+// grouped unconditionally. Input order is provider priority, so
+// the first member of a group is the one whose fields are shown.
+// ============================================================
+
+/// Minimum score for a result to join an existing group
+const MERGE_THRESHOLD: f32 = 0.8;
+
+/// Group duplicate results into one `AnimeResult` per show.
+/// `anchors` are provider IDs known (from mappings) to be the show being looked for.
+pub fn merge_results(results: Vec<AnimeResult>, anchors: &[MediaId]) -> Vec<AnimeResult> {
+    let mut groups: Vec<Group> = Vec::new();
+
+    let (anchored, rest): (Vec<_>, Vec<_>) = results.into_iter().partition(|r| is_anchored(r, anchors));
+    if !anchored.is_empty() {
+        groups.push(Group { members: anchored, confidence: 1.0 });
+    }
+
+    for result in rest {
+        let best = groups.iter()
+            .enumerate()
+            .filter(|(_, g)| g.accepts(&result))
+            .map(|(i, g)| (i, match_score(&g.members[0], &result)))
+            .filter(|(_, score)| *score >= MERGE_THRESHOLD)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        match best {
+            Some((i, score)) => {
+                let group = &mut groups[i];
+                group.confidence = group.confidence.min(score);
+                group.members.push(result);
+            },
+            None => groups.push(Group { members: vec![result], confidence: 1.0 }),
+        }
+    }
+
+    groups.into_iter().map(Group::into_result).collect()
+}
+
+struct Group {
+    members: Vec<AnimeResult>,
+    confidence: f32,
+}
+
+impl Group {
+    /// A provider may appear twice only for different audio (Anitaku's separate dub listing)
+    fn accepts(&self, result: &AnimeResult) -> bool {
+        self.members.iter()
+            .filter(|m| m.provider == result.provider)
+            .all(|m| m.variant_ids.keys().all(|mode| !result.variant_ids.contains_key(mode)))
+    }
+
+    fn into_result(self) -> AnimeResult {
+        let mut members = self.members.into_iter();
+        let mut merged = members.next().expect("groups are never empty");
+        let mut matches = vec![provider_match(&merged)];
+
+        for member in members {
+            // Keep every provider's naming so search ranking can match any of them
+            for title in std::iter::once(&member.title).chain(&member.alt_titles) {
+                if *title != merged.title && !merged.alt_titles.contains(title) {
+                    merged.alt_titles.push(title.clone());
+                }
+            }
+            for lang in &member.available_languages {
+                if !merged.available_languages.contains(lang) {
+                    merged.available_languages.push(lang.clone());
+                }
+            }
+            // Higher-priority providers keep their ID for a mode
+            for (mode, id) in &member.variant_ids {
+                merged.variant_ids.entry(mode.clone()).or_insert_with(|| id.clone());
+            }
+            merged.episode_count = merged.episode_count.max(member.episode_count);
+            merged.is_multi_audio |= member.is_multi_audio;
+            matches.push(provider_match(&member));
+        }
+
+        merged.is_multi_audio |= merged.variant_ids.len() > 1;
+        merged.matches = matches;
+        merged.match_confidence = Some(self.confidence);
+        merged
+    }
+}
+
+fn provider_match(result: &AnimeResult) -> ProviderMatch {
+    ProviderMatch {
+        provider: result.provider.clone(),
+        id: result.id.clone(),
+        title: result.title.clone(),
+        languages: result.available_languages.clone(),
+        variant_ids: result.variant_ids.clone(),
+        episode_count: result.episode_count,
+    }
+}
+
+fn is_anchored(result: &AnimeResult, anchors: &[MediaId]) -> bool {
+    anchors.contains(&result.id) || result.variant_ids.values().any(|id| anchors.contains(id))
+}
+
+/// Title similarity adjusted by how well the episode counts agree, clamped to 0..1
+fn match_score(a: &AnimeResult, b: &AnimeResult) -> f32 {
+    let mut score = title_similarity(&a.title, &b.title);
+
+    if let (Some(x), Some(y)) = (a.episode_count, b.episode_count) {
+        if x > 0 && y > 0 {
+            // Airing shows drift by an episode or two between providers
+            let tolerance = (x.max(y) / 10).max(2);
+            let diff = (x - y).abs();
+            if diff == 0 {
+                score += 0.1;
+            } else if diff > tolerance {
+                score -= 0.25;
+            }
+        }
+    }
+    score.clamp(0.0, 1.0)
+}
+
+/// 1.0 for identical normalised titles, otherwise the Dice coefficient of their
+/// word sets, halved when the numbers differ ("Season 2", "Part 2", "2nd").
+fn title_similarity(a: &str, b: &str) -> f32 {
+    if normalize_title(a) == normalize_title(b) {
+        return 1.0;
+    }
+
+    let (ta, tb) = (title_tokens(a), title_tokens(b));
+    if ta.is_empty() || tb.is_empty() {
+        return 0.0;
+    }
+    let shared = ta.intersection(&tb).count();
+    let mut score = 2.0 * shared as f32 / (ta.len() + tb.len()) as f32;
+
+    if numbers(a) != numbers(b) {
+        score *= 0.5;
+    }
+    score
+}
+
+/// Lowercase alphanumerics only, without a trailing "(Dub)" marker
+pub fn normalize_title(title: &str) -> String {
+    let lower = title.to_lowercase();
+    let trimmed = lower.trim().trim_end_matches("(dub)");
+    trimmed.chars().filter(|c| c.is_alphanumeric()).collect()
+}
+
+/// Lowercase words, ignoring the "dub" marker
+pub fn title_tokens(title: &str) -> HashSet<String> {
+    title.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty() && *t != "dub")
+        .map(|t| t.to_string())
+        .collect()
+}
+
+/// Leading digits of each word ("2nd" -> 2), sorted, repeats kept ("Season 2 Part 2")
+fn numbers(title: &str) -> Vec<u32> {
+    let mut numbers: Vec<u32> = title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter_map(|t| {
+            let digits: String = t.chars().take_while(|c| c.is_ascii_digit()).collect();
+            digits.parse().ok()
+        })
+        .collect();
+    numbers.sort_unstable();
+    numbers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ids::TranslationType;
+    use std::collections::HashMap;
+
+    fn result(provider: &str, id: MediaId, title: &str, modes: &[&str], episodes: i32) -> AnimeResult {
+        AnimeResult {
+            id: id.clone(),
+            title: title.to_string(),
+            url: String::new(),
+            image: String::new(),
+            release_date: None,
+            language: None,
+            is_multi_audio: false,
+            available_languages: modes.iter().map(|m| if *m == "dub" { "English" } else { "Japanese" }.to_string()).collect(),
+            provider: provider.to_string(),
+            episode_count: Some(episodes),
+            variant_ids: modes.iter().map(|m| (m.to_string(), id.clone())).collect::<HashMap<_, _>>(),
+            alt_titles: Vec::new(),
+            matches: Vec::new(),
+            match_confidence: None,
+            relevance: None,
+            format: None,
+            rating: None,
+        }
+    }
+
+    fn anitaku(slug: &str) -> MediaId {
+        MediaId::Anitaku { slug: slug.to_string() }
+    }
+
+    fn hianime(slug: &str) -> MediaId {
+        MediaId::HiAnime { slug: slug.to_string(), category: TranslationType::Sub, dub_episodes: None }
+    }
+
+    #[test]
+    fn test_groups_same_show_but_not_sequels() {
+        let results = vec![
+            result("hianime", hianime("frieren-18542"), "Frieren: Beyond Journey's End", &["sub", "dub"], 28),
+            result("anitaku", anitaku("sousou-no-frieren"), "Frieren: Beyond Journey's End", &["sub"], 28),
+            result("anitaku", anitaku("sousou-no-frieren-dub"), "Frieren: Beyond Journey's End (Dub)", &["dub"], 27),
+            result("anitaku", anitaku("sousou-no-frieren-2nd-season"), "Frieren: Beyond Journey's End 2nd Season", &["sub"], 4),
+        ];
+
+        let merged = merge_results(results, &[]);
+        assert_eq!(merged.len(), 2);
+
+        let show = &merged[0];
+        assert_eq!(show.provider, "hianime");
+        assert_eq!(show.matches.len(), 3);
+        assert_eq!(show.variant_ids.get("dub"), Some(&hianime("frieren-18542")));
+        assert!(show.is_multi_audio);
+        assert_eq!(show.match_confidence, Some(1.0));
+        assert_eq!(merged[1].matches.len(), 1);
+    }
+
+    #[test]
+    fn test_anchors_group_differently_titled_results() {
+        let allanime = MediaId::AllAnime { show_id: "ReooPAxPMsHM4KPMY".to_string(), mode: TranslationType::Sub };
+        let results = vec![
+            result("allanime", allanime.clone(), "Sousou no Frieren", &["sub"], 28),
+            result("anitaku", anitaku("sousou-no-frieren"), "Sousou no Frieren", &["sub"], 28),
+            result("hianime", hianime("frieren-18542"), "Frieren: Beyond Journey's End", &["sub"], 28),
+        ];
+
+        let unanchored = merge_results(results.clone(), &[]);
+        assert_eq!(unanchored.len(), 2);
+
+        let anchors = vec![allanime, hianime("frieren-18542")];
+        let merged = merge_results(results, &anchors);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].matches.len(), 3);
+        assert_eq!(merged[0].provider, "allanime");
+    }
+
+    #[test]
+    fn test_title_similarity_penalises_different_numbers() {
+        assert_eq!(title_similarity("Sousou no Frieren", "Sousou no Frieren (Dub)"), 1.0);
+        assert!(title_similarity("Mushoku Tensei Season 2", "Mushoku Tensei") < MERGE_THRESHOLD);
+        assert!(title_similarity("Mushoku Tensei Season 2", "Mushoku Tensei: Season 2 Part 2") < MERGE_THRESHOLD);
+    }
+}
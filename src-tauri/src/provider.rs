@@ -0,0 +1,292 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use futures::future::join_all;
+use crate::cache::{self, DiskCache};
+use crate::error::{NezukoError, Result};
+use crate::health::{HealthMonitor, HealthReport};
+use crate::anime::{AnimeResult, Episode, StreamStatus, VideoSource};
+use crate::extractors::mapping::ProviderMappings;
+use crate::ids::{EpisodeId, MediaId, TranslationType};
+use crate::singleflight::SingleFlight;
+use crate::extractors::{allanime::AllAnimeProvider, anitaku::AnitakuProvider, hianime::HiAnimeProvider};
+
+/// Static description of a provider (used for routing and exposed to the UI)
+#[derive(Serialize, Debug, Clone)]
+pub struct ProviderInfo {
+    pub id: &'static str,   // "allanime", "hianime", "anitaku"
+    pub name: &'static str, // "AllAnime", "HiAnime", "Anitaku"
+    pub tier: &'static str, // "Diamond", "Gold", "Silver"
+}
+
+/// A streaming source that can search, list episodes and resolve streams.
+#[async_trait]
+pub trait Provider: Send + Sync {
+    fn info(&self) -> ProviderInfo;
+
+    async fn search(&self, query: &str) -> Result<Vec<AnimeResult>>;
+
+    async fn episodes(&self, anime_id: &MediaId) -> Result<Vec<Episode>>;
+
+    async fn stream(&self, episode_id: &EpisodeId) -> Result<VideoSource>;
+
+    /// This provider's show ID from AniZip mappings, if it has one for the language
+    fn media_id_from_mappings(&self, _mappings: &ProviderMappings, _language: TranslationType) -> Option<MediaId> {
+        None
+    }
+
+    /// Every source for the episode, best first, including ones that failed to resolve.
+    /// Providers with a single source just wrap `stream`.
+    async fn stream_candidates(&self, episode_id: &EpisodeId) -> Result<Vec<VideoSource>> {
+        Ok(vec![self.stream(episode_id).await?])
+    }
+}
+
+/// Ordered set of enabled providers. Order is priority (Diamond → Gold → Silver).
+/// Identical concurrent searches / episode lists / stream lookups share one upstream call,
+/// and successful results are kept in the disk cache when one is attached.
+/// Providers whose circuit breaker is open are skipped until a probe succeeds.
+pub struct ProviderRegistry {
+    providers: Vec<Arc<dyn Provider>>,
+    cache: Option<Arc<DiskCache>>,
+    health: Arc<HealthMonitor>,
+    search_flights: SingleFlight<Result<Vec<AnimeResult>>>,
+    episode_flights: SingleFlight<Result<Vec<Episode>>>,
+    stream_flights: SingleFlight<Result<VideoSource>>,
+    candidate_flights: SingleFlight<Result<Vec<VideoSource>>>,
+}
+
+impl Default for ProviderRegistry {
+    fn default() -> Self {
+        Self::new(vec![
+            Arc::new(AllAnimeProvider),
+            Arc::new(HiAnimeProvider),
+            Arc::new(AnitakuProvider),
+        ])
+    }
+}
+
+impl ProviderRegistry {
+    pub fn new(providers: Vec<Arc<dyn Provider>>) -> Self {
+        Self {
+            providers,
+            cache: None,
+            health: Arc::new(HealthMonitor::default()),
+            search_flights: SingleFlight::default(),
+            episode_flights: SingleFlight::default(),
+            stream_flights: SingleFlight::default(),
+            candidate_flights: SingleFlight::default(),
+        }
+    }
+
+    pub fn with_cache(mut self, cache: Arc<DiskCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn infos(&self) -> Vec<ProviderInfo> {
+        self.providers.iter().map(|p| p.info()).collect()
+    }
+
+    pub fn health(&self) -> Vec<HealthReport> {
+        self.providers.iter().map(|p| self.health.report(p.info().id)).collect()
+    }
+
+    /// Every provider's show ID (any audio mode) that AniZip maps to this AniList entry
+    pub fn mapped_ids(&self, mappings: &ProviderMappings) -> Vec<MediaId> {
+        self.providers.iter()
+            .flat_map(|p| [TranslationType::Sub, TranslationType::Dub, TranslationType::Raw].map(|lang| p.media_id_from_mappings(mappings, lang)))
+            .flatten()
+            .collect()
+    }
+
+    /// Look up a provider by its short ID ("allanime")
+    pub fn get(&self, provider_id: &str) -> Option<Arc<dyn Provider>> {
+        self.providers.iter().find(|p| p.info().id == provider_id).cloned()
+    }
+
+    /// Like `get`, but an error when the provider is not registered
+    fn require(&self, provider_id: &str) -> Result<Arc<dyn Provider>> {
+        self.get(provider_id).ok_or_else(|| NezukoError::unsupported(provider_id, "provider is not enabled"))
+    }
+
+    /// Search a single provider (cached and behind its circuit breaker)
+    pub async fn search_provider(&self, provider_id: &str, query: &str) -> Result<Vec<AnimeResult>> {
+        let provider = self.require(provider_id)?;
+        search_one(&provider, self.cache.clone(), &self.health, &query.trim().to_lowercase()).await
+    }
+
+    /// Search every provider in parallel. Failures are logged and skipped.
+    pub async fn search(&self, query: &str) -> Result<Vec<AnimeResult>> {
+        let key = query.trim().to_lowercase();
+        let providers = self.providers.clone();
+        let cache = self.cache.clone();
+        let health = self.health.clone();
+        self.search_flights.run(&key, || search_all(providers, cache, health, key.clone())).await
+    }
+
+    /// Breaker-guarded, uncached call for provider-specific APIs
+    pub async fn call<T>(&self, provider_id: &str, call: impl Future<Output = Result<T>>) -> Result<T> {
+        guarded(&self.health, provider_id, call).await
+    }
+
+    /// Cached, breaker-guarded call for provider-specific APIs outside the `Provider` trait
+    /// (filtered catalog search, server lists). Empty results are cached too.
+    pub async fn call_cached<T, Fut>(&self, provider_id: &str, key: &str, ttl: Duration, call: Fut) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+        Fut: Future<Output = Result<T>>,
+    {
+        if let Some(hit) = self.cache.as_ref().and_then(|c| c.get(key)) {
+            return Ok(hit);
+        }
+        let value = self.call(provider_id, call).await?;
+        if let Some(cache) = &self.cache {
+            cache.put(key, &value, ttl);
+        }
+        Ok(value)
+    }
+
+    pub async fn episodes(&self, anime_id: &MediaId) -> Result<Vec<Episode>> {
+        let provider = self.require(anime_id.provider())?;
+        let key = DiskCache::key(cache::EPISODES, anime_id.provider(), &anime_id.to_string());
+        if let Some(hit) = self.cache.as_ref().and_then(|c| c.get(&key)) {
+            return Ok(hit);
+        }
+
+        let cache = self.cache.clone();
+        let health = self.health.clone();
+        let anime_id = anime_id.clone();
+        let cache_key = key.clone();
+        self.episode_flights.run(&key, || async move {
+            let episodes = guarded(&health, anime_id.provider(), provider.episodes(&anime_id)).await?;
+            if let Some(cache) = cache.filter(|_| !episodes.is_empty()) {
+                cache.put(&cache_key, &episodes, cache::EPISODES_TTL);
+            }
+            Ok(episodes)
+        }).await
+    }
+
+    pub async fn stream(&self, episode_id: &EpisodeId) -> Result<VideoSource> {
+        let provider = self.require(episode_id.provider())?;
+        let key = DiskCache::key(cache::STREAM, episode_id.provider(), &episode_id.to_string());
+        if let Some(hit) = self.cache.as_ref().and_then(|c| c.get::<VideoSource>(&key)) {
+            return Ok(hit.with_current_proxy());
+        }
+
+        let cache = self.cache.clone();
+        let health = self.health.clone();
+        let episode_id = episode_id.clone();
+        let cache_key = key.clone();
+        self.stream_flights.run(&key, || async move {
+            let source = guarded(&health, episode_id.provider(), provider.stream(&episode_id)).await?;
+            if let Some(cache) = cache {
+                cache.put(&cache_key, &source, cache::stream_ttl(&source.url));
+            }
+            Ok(source)
+        }).await
+    }
+
+    pub async fn stream_candidates(&self, episode_id: &EpisodeId) -> Result<Vec<VideoSource>> {
+        let provider = self.require(episode_id.provider())?;
+        let key = DiskCache::key(cache::CANDIDATES, episode_id.provider(), &episode_id.to_string());
+        if let Some(hit) = self.cache.as_ref().and_then(|c| c.get::<Vec<VideoSource>>(&key)) {
+            return Ok(hit.into_iter().map(VideoSource::with_current_proxy).collect());
+        }
+
+        let cache = self.cache.clone();
+        let health = self.health.clone();
+        let episode_id = episode_id.clone();
+        let cache_key = key.clone();
+        self.candidate_flights.run(&key, || async move {
+            let candidates = guarded(&health, episode_id.provider(), provider.stream_candidates(&episode_id)).await?;
+            // Valid until the first resolved URL expires; failures are never cached on their own
+            let ttl = candidates.iter()
+                .filter(|c| c.status == StreamStatus::Resolved)
+                .map(|c| cache::stream_ttl(&c.url))
+                .min();
+            if let (Some(cache), Some(ttl)) = (cache, ttl) {
+                cache.put(&cache_key, &candidates, ttl);
+            }
+            Ok(candidates)
+        }).await
+    }
+}
+
+/// Run one provider call through its circuit breaker, recording outcome and latency
+async fn guarded<T>(health: &HealthMonitor, provider_id: &str, call: impl Future<Output = Result<T>>) -> Result<T> {
+    if !health.allow(provider_id) {
+        return Err(NezukoError::unavailable(provider_id, "circuit open after repeated failures"));
+    }
+    let started = Instant::now();
+    let result = call.await;
+    health.record(provider_id, started.elapsed(), &result);
+    result
+}
+
+async fn search_one(
+    provider: &Arc<dyn Provider>,
+    cache: Option<Arc<DiskCache>>,
+    health: &HealthMonitor,
+    query: &str,
+) -> Result<Vec<AnimeResult>> {
+    let provider_id = provider.info().id;
+    let key = DiskCache::key(cache::SEARCH, provider_id, query);
+    if let Some(hit) = cache.as_ref().and_then(|c| c.get(&key)) {
+        return Ok(hit);
+    }
+    let results = guarded(health, provider_id, provider.search(query)).await?;
+    if let Some(cache) = cache.filter(|_| !results.is_empty()) {
+        cache.put(&key, &results, cache::SEARCH_TTL);
+    }
+    Ok(results)
+}
+
+/// Search each provider (from cache where possible) and concatenate the results
+async fn search_all(
+    providers: Vec<Arc<dyn Provider>>,
+    cache: Option<Arc<DiskCache>>,
+    health: Arc<HealthMonitor>,
+    query: String,
+) -> Result<Vec<AnimeResult>> {
+    let searches = providers.iter().map(|p| search_one(p, cache.clone(), &health, &query));
+    let responses = join_all(searches).await;
+
+    let mut results = Vec::new();
+    for (provider, res) in providers.iter().zip(responses) {
+        let name = provider.info().name;
+        match res {
+            Ok(r) => {
+                println!("DEBUG: {} returned {} results", name, r.len());
+                results.extend(r);
+            },
+            Err(e) => println!("DEBUG: {} search error: {}", name, e),
+        }
+    }
+
+    println!("DEBUG: Total results found: {}", results.len());
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_lookup() {
+        let registry = ProviderRegistry::default();
+        let ids: Vec<&str> = registry.infos().iter().map(|i| i.id).collect();
+        assert_eq!(ids, vec!["allanime", "hianime", "anitaku"]);
+        assert_eq!(registry.get("hianime").unwrap().info().tier, "Gold");
+    }
+
+    #[test]
+    fn test_disabled_provider_is_rejected() {
+        let registry = ProviderRegistry::new(vec![Arc::new(AllAnimeProvider)]);
+        assert!(registry.get("hianime").is_none());
+        assert!(registry.require("anitaku").is_err());
+    }
+}
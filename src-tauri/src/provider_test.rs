@@ -3,8 +3,9 @@
 
 #[cfg(test)]
 mod provider_test {
-    use crate::extractors::{allanime, hianime};
+    use crate::extractors::{allanime, hianime, anitaku::AnitakuProvider};
     use crate::anime;
+    use crate::provider::Provider;
 
     const TEST_ANIME: &str = "Demon Slayer";
 
@@ -95,7 +96,7 @@ mod provider_test {
                                 
                                 // Get sources (SUB)
                                 println!("\n  --- SUB Sources ---");
-                                match hianime::get_sources(&ep1.episode_id, "sub").await {
+                                match hianime::get_sources(&ep1.episode_id, "sub", None).await {
                                     Ok(data) => {
                                         println!("  SOURCES (SUB): {} streams", data.sources.len());
                                         for s in &data.sources {
@@ -122,7 +123,7 @@ mod provider_test {
                                 
                                 // Get sources (DUB)
                                 println!("\n  --- DUB Sources ---");
-                                match hianime::get_sources(&ep1.episode_id, "dub").await {
+                                match hianime::get_sources(&ep1.episode_id, "dub", None).await {
                                     Ok(data) => {
                                         println!("  SOURCES (DUB): {} streams", data.sources.len());
                                         for s in &data.sources {
@@ -158,13 +159,13 @@ mod provider_test {
                 let dub_anime = results.iter().find(|r| r.title.to_lowercase().contains("dub"));
                 
                 if let Some(sub_a) = sub_anime {
-                    match anime::get_anitaku_episodes(&sub_a.id).await {
+                    match AnitakuProvider.episodes(&sub_a.id).await {
                         Ok(eps) => {
                             println!("  EPISODES (SUB '{}'): {} total", sub_a.title, eps.len());
                             if let Some(ep1) = eps.first() {
                                 println!("  EP1: id={}, num={}", ep1.id, ep1.number);
                                 
-                                match anime::get_anitaku_stream(&ep1.id).await {
+                                match AnitakuProvider.stream(&ep1.id).await {
                                     Ok(src) => {
                                         println!("  STREAM: provider={} m3u8={} quality={}", src.provider, src.is_m3u8, src.quality);
                                         let p = if src.url.len() > 80 { &src.url[..80] } else { &src.url };
@@ -181,7 +182,7 @@ mod provider_test {
                 }
                 
                 if let Some(dub_a) = dub_anime {
-                    match anime::get_anitaku_episodes(&dub_a.id).await {
+                    match AnitakuProvider.episodes(&dub_a.id).await {
                         Ok(eps) => {
                             println!("  EPISODES (DUB '{}'): {} total", dub_a.title, eps.len());
                         },
@@ -1,24 +1,43 @@
 use warp::Filter;
+use futures::future::Either;
 use futures::StreamExt;
 use std::collections::HashMap;
+use std::sync::OnceLock;
+use crate::adfilter;
+use crate::chapters;
+use crate::hls;
+use crate::proxy_guard;
+use crate::segment_cache::{self, CachedSegment};
+use crate::http::{self, RequestExt};
 
 pub async fn start_proxy_server() {
-    // Handle both GET and HEAD for /proxy?url=...&headers=...
+    // Handle both GET and HEAD for /proxy?url=...&headers=..., passing the player's Range on
     let proxy_route_get = warp::path("proxy")
         .and(warp::get())
+        .and(warp::method())
+        .and(warp::header::optional::<String>("range"))
         .and(warp::query::<ProxyParams>())
         .and_then(handle_proxy_request);
 
     let proxy_route_head = warp::path("proxy")
         .and(warp::head())
+        .and(warp::method())
+        .and(warp::header::optional::<String>("range"))
         .and(warp::query::<ProxyParams>())
         .and_then(handle_proxy_request);
 
-    let proxy_routes = proxy_route_get.or(proxy_route_head);
+    // Generated WebVTT chapters for skip segments: /chapters?segments=intro:31-121,...
+    let chapters_route = warp::path("chapters")
+        .and(warp::get())
+        .and(warp::query::<ChaptersParams>())
+        .map(handle_chapters_request);
+
+    let proxy_routes = proxy_route_get.or(proxy_route_head).or(chapters_route);
 
-    // Allow ALL origins, methods, and headers for CORS
+    // Only the app's own webview may read responses cross-origin
+    let config = proxy_guard::config();
     let cors = warp::cors()
-        .allow_any_origin()
+        .allow_origins(config.allowed_origins.iter().map(String::as_str))
         .allow_methods(vec!["GET", "POST", "OPTIONS", "HEAD"])
         .allow_headers(vec![
             "Content-Type", "User-Agent", "Referer", "Cookie", 
@@ -30,42 +49,214 @@ pub async fn start_proxy_server() {
 
     let routes = proxy_routes.with(cors);
 
-    println!("Starting Proxy Server on 0.0.0.0:3030");
-    warp::serve(routes).run(([0, 0, 0, 0], 3030)).await;
+    // Fall back to any free port when the configured one is taken (release builds only
+    // reach the proxy directly, so they follow it; the Vite dev proxy expects the default)
+    let (bind, port) = (config.bind, config.port);
+    let (addr, serving) = match warp::serve(routes.clone()).try_bind_ephemeral((bind, port)) {
+        Ok((addr, serving)) => (addr, Either::Left(serving)),
+        Err(e) => {
+            println!("PROXY: Port {} unavailable ({}), using a free port", port, e);
+            let (addr, serving) = warp::serve(routes).bind_ephemeral((bind, 0));
+            (addr, Either::Right(serving))
+        },
+    };
+    if PROXY_BASE.set(base_for_port(addr.port())).is_err() {
+        println!("PROXY: Base URL was read before the server bound; keeping {}", proxy_base());
+    }
+
+    println!("Starting Proxy Server on {} (reachable at {})", addr, proxy_base());
+    serving.await;
+}
+
+// ============================================================
+// Proxy URLs
+// ------------------------------------------------------------
+// Every URL handed to the webview that points at this server is
+// built here. Dev builds go through the Vite dev server
+// (`/api/*` is forwarded to the proxy, see vite.config.ts); packaged
+// builds have no dev server and talk to the proxy on loopback.
+// `NEZUKO_PROXY_BASE` overrides both. Proxy URLs are signed (see
+// proxy_guard.rs), so they are only valid for the run that built them.
+// ============================================================
+
+const DEV_BASE: &str = "http://localhost:1420/api";
+
+static PROXY_BASE: OnceLock<String> = OnceLock::new();
+
+fn base_for_port(port: u16) -> String {
+    if let Ok(base) = std::env::var("NEZUKO_PROXY_BASE") {
+        return base.trim_end_matches('/').to_string();
+    }
+    if cfg!(debug_assertions) {
+        DEV_BASE.to_string()
+    } else {
+        format!("http://127.0.0.1:{}", port)
+    }
+}
+
+/// Where the webview reaches the proxy, without a trailing slash
+pub fn proxy_base() -> &'static str {
+    PROXY_BASE.get_or_init(|| base_for_port(proxy_guard::config().port))
+}
+
+/// URL of one of the proxy's routes ("proxy", "chapters")
+pub fn endpoint(route: &str) -> String {
+    format!("{}/{}", proxy_base(), route)
+}
+
+/// Proxied URL for `target`, fetched upstream with `headers` (a JSON object)
+pub fn proxy_url(target: &str, headers: &str) -> String {
+    proxy_url_at(&endpoint("proxy"), target, headers)
+}
+
+fn proxy_url_at(proxy_route: &str, target: &str, headers: &str) -> String {
+    format!(
+        "{}?url={}&headers={}&sig={}",
+        proxy_route,
+        urlencoding::encode(target),
+        urlencoding::encode(headers),
+        proxy_guard::sign(target, headers)
+    )
+}
+
+/// Point a proxy URL built by an earlier run (e.g. from the disk cache) at this run's
+/// proxy, re-signed with this run's key
+pub fn rebase(url: &str) -> String {
+    if let Some(pos) = url.find("/chapters?") {
+        return format!("{}{}", proxy_base(), &url[pos..]);
+    }
+    if !url.contains("/proxy?") {
+        return url.to_string();
+    }
+    let Ok(parsed) = reqwest::Url::parse(url) else {
+        return url.to_string();
+    };
+    let param = |name: &str| parsed.query_pairs().find(|(k, _)| k == name).map(|(_, v)| v.into_owned());
+    let Some(target) = param("url") else {
+        return url.to_string();
+    };
+    let mut rebased = proxy_url(&target, &param("headers").unwrap_or_default());
+    if let Some(quality) = param("quality") {
+        rebased.push_str(&format!("&quality={}", urlencoding::encode(&quality)));
+    }
+    rebased
 }
 
 #[derive(serde::Deserialize)]
 struct ProxyParams {
     url: String,
     headers: Option<String>,
+    /// Serve a master playlist reduced to one variant ("1080p", "720", "best", "lowest")
+    quality: Option<String>,
+    /// HMAC of `url` + `headers` from `proxy_url`
+    sig: Option<String>,
 }
 
-async fn handle_proxy_request(params: ProxyParams) -> Result<impl warp::Reply, warp::Rejection> {
+/// Upstream URL and headers behind one of our proxy URLs
+pub fn proxy_target(proxy_url: &str) -> Option<(String, HashMap<String, String>)> {
+    let parsed = reqwest::Url::parse(proxy_url).ok()?;
+    let mut url = None;
+    let mut headers = HashMap::new();
+    for (key, value) in parsed.query_pairs() {
+        match key.as_ref() {
+            "url" => url = Some(value.into_owned()),
+            "headers" => headers = serde_json::from_str(&value).unwrap_or_default(),
+            _ => {},
+        }
+    }
+    Some((url?, headers))
+}
+
+#[derive(serde::Deserialize)]
+struct ChaptersParams {
+    segments: String,
+}
+
+fn handle_chapters_request(params: ChaptersParams) -> warp::reply::Response {
+    let segments = chapters::parse_segments(&params.segments);
+    println!("PROXY: Serving chapters for {} segments", segments.len());
+
+    let mut reply = warp::reply::Response::new(warp::hyper::Body::from(chapters::to_webvtt(&segments)));
+    reply.headers_mut().insert("content-type", "text/vtt; charset=utf-8".parse().unwrap());
+    reply
+}
+
+fn forbidden(reason: &str) -> warp::reply::Response {
+    let mut reply = warp::reply::Response::new(warp::hyper::Body::from(reason.to_string()));
+    *reply.status_mut() = warp::hyper::StatusCode::FORBIDDEN;
+    reply
+}
+
+/// Upstream response headers the player needs for seeking and caching
+const PASSTHROUGH_HEADERS: &[&str] = &[
+    "content-type", "content-length", "content-range", "accept-ranges", "last-modified", "etag",
+];
+
+async fn handle_proxy_request(
+    method: warp::http::Method,
+    range: Option<String>,
+    params: ProxyParams,
+) -> Result<impl warp::Reply, warp::Rejection> {
     println!("PROXY: Received request for URL: {}", &params.url);
-    let client = reqwest::Client::new();
-    let mut request_builder = client.get(&params.url);
+
+    let headers_json = params.headers.as_deref().unwrap_or_default();
+    if !params.sig.as_deref().is_some_and(|sig| proxy_guard::verify(&params.url, headers_json, sig)) {
+        println!("PROXY: Refusing unsigned or tampered request for {}", params.url);
+        return Ok(forbidden("invalid signature"));
+    }
+    // Names can resolve to internal hosts even though we signed them; check on every request.
+    // Redirect hops and the address actually connected to are checked by the guarded client.
+    if let Err(e) = proxy_guard::check_target(&params.url).await {
+        println!("PROXY: Refusing {}: {}", params.url, e);
+        return Ok(forbidden("target not allowed"));
+    }
+    forward(method, range, params, http::client()).await
+}
+
+/// Fetch a verified target through `upstream` and reply with it (rewritten, cached or streamed)
+async fn forward(
+    method: warp::http::Method,
+    range: Option<String>,
+    params: ProxyParams,
+    upstream: &http::HttpClient,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    // Playlists are always fetched whole (they get rewritten); anything else may be a
+    // seekable file, so the player's Range goes upstream and HEAD stays a HEAD
+    let is_head = method == warp::http::Method::HEAD;
+    let looks_like_playlist = params.url.contains(".m3u8");
+
+    // Whole-segment GETs of a known playlist go through the segment cache
+    let cacheable = !is_head && range.is_none() && segment_cache::cache().is_segment(&params.url);
+    if cacheable {
+        if let Some(segment) = segment_cache::cache().get(&params.url) {
+            println!("PROXY: Segment cache hit for {}", params.url);
+            spawn_prefetch(&params.url);
+            return Ok(segment_reply(&params.url, segment));
+        }
+    }
+
+    // Shared client: default User-Agent is added unless the caller overrides it
+    let mut request_builder = if is_head {
+        upstream.head(&params.url)
+    } else {
+        upstream.get_streaming(&params.url)
+    };
+    if let Some(range) = range.as_deref().filter(|_| !looks_like_playlist) {
+        request_builder = request_builder.header("Range", range);
+    }
 
     // Add custom headers if provided (JSON string)
-    let mut has_ua = false;
     if let Some(ref headers_json) = params.headers {
         if let Ok(headers_map) = serde_json::from_str::<HashMap<String, String>>(headers_json) {
             for (key, value) in headers_map {
-                if key.eq_ignore_ascii_case("user-agent") {
-                    has_ua = true;
-                }
                 request_builder = request_builder.header(key, value);
             }
         }
     }
 
-    // Always add a User-Agent if not present (some APIs block empty UA)
-    if !has_ua {
-        request_builder = request_builder.header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36");
-    }
-
     println!("PROXY: Sending request to {}", params.url);
 
-    match request_builder.send().await {
+    match upstream.send("proxy", request_builder).await {
         Ok(response) => {
             let status = response.status();
             let headers = response.headers().clone();
@@ -79,78 +270,79 @@ async fn handle_proxy_request(params: ProxyParams) -> Result<impl warp::Reply, w
                 || content_type.contains("m3u8")
                 || params.url.contains("/hls/"); // ZephyrFlick uses /hls/ paths
             
+            if is_head {
+                // Same status and headers a GET would get, without the body; a rewritten
+                // playlist's length differs from upstream's, so it is left out
+                let mut reply = warp::reply::Response::new(warp::hyper::Body::empty());
+                *reply.status_mut() = warp::hyper::StatusCode::from_u16(status.as_u16()).unwrap_or(warp::hyper::StatusCode::INTERNAL_SERVER_ERROR);
+                copy_headers(&mut reply, &headers, if is_m3u8 { &["content-type"] } else { PASSTHROUGH_HEADERS });
+                return Ok(reply);
+            }
+
             // For m3u8 files, we need to rewrite relative URLs to absolute URLs
             if is_m3u8 {
+                // Relative URIs are relative to where the playlist came from after redirects
+                let final_url = response.url().clone();
                 let body_text = response.text().await.unwrap_or_default();
-                
-                // Extract base URL from original URL
-                let base_url = if let Some(pos) = params.url.rfind('/') {
-                    &params.url[..=pos]
-                } else {
-                    &params.url
-                };
-                
-                // Extract origin from URL
-                let origin = reqwest::Url::parse(&params.url)
-                    .map(|u| format!("{}://{}", u.scheme(), u.host_str().unwrap_or("")))
-                    .unwrap_or_default();
-                
-                // Reuse headers for segment requests
-                let headers_param = params.headers.clone().unwrap_or_default();
-                
-                // Proxy base URL (Vite proxy)
-                let proxy_base = "http://localhost:1420/api/proxy";
-                
-                println!("PROXY: Rewriting m3u8, base_url: {}, origin: {}", base_url, origin);
-                
-                // Rewrite all URLs to go through our proxy
-                let mut rewritten = String::new();
-                for line in body_text.lines() {
-                    let trimmed = line.trim();
-                    if trimmed.is_empty() || trimmed.starts_with('#') {
-                        // Preserve comments and empty lines, but check for URI= in comments
-                        if trimmed.contains("URI=\"") {
-                            // Rewrite URIs in #EXT-X-KEY or similar tags
-                            let rewritten_line = rewrite_uri_in_tag(trimmed, &origin, base_url, proxy_base, &headers_param);
-                            rewritten.push_str(&rewritten_line);
-                        } else {
-                            rewritten.push_str(line);
+
+                let rewritten = match hls::Playlist::parse(&body_text) {
+                    Ok(mut playlist) => {
+                        // Pin a master to the requested variant before its URIs are rewritten
+                        if let (Some(preference), hls::Playlist::Master(master)) = (params.quality.as_deref().and_then(hls::QualityPreference::parse), &mut playlist) {
+                            if master.retain_variant(preference) {
+                                println!("PROXY: Serving single-variant master ({:?})", preference);
+                            }
                         }
-                    } else {
-                        // Convert to absolute URL first
-                        let absolute_url = if trimmed.starts_with("http://") || trimmed.starts_with("https://") {
-                            trimmed.to_string()
-                        } else if trimmed.starts_with('/') {
-                            format!("{}{}", origin, trimmed)
-                        } else {
-                            format!("{}{}", base_url, trimmed)
-                        };
-                        
-                        // Wrap through proxy
-                        let proxied = format!(
-                            "{}?url={}&headers={}",
-                            proxy_base,
-                            urlencoding::encode(&absolute_url),
-                            urlencoding::encode(&headers_param)
-                        );
-                        rewritten.push_str(&proxied);
-                    }
-                    rewritten.push('\n');
-                }
-                
+
+                        // Reuse headers for segment requests
+                        let headers_param = params.headers.clone().unwrap_or_default();
+                        println!("PROXY: Rewriting m3u8 relative to {}", final_url);
+                        playlist.resolve_uris(&final_url);
+
+                        // Media playlists (not masters) lose injected ads, then tell the segment
+                        // cache what comes next
+                        if let hls::Playlist::Media(media) = &mut playlist {
+                            if proxy_guard::config().strip_ads {
+                                for run in adfilter::strip_ads(media) {
+                                    println!("PROXY: Stripped ad run from {}: {}", params.url, run);
+                                }
+                            }
+                            let segments = media.segments.iter().map(|s| s.uri.clone()).collect();
+                            segment_cache::cache().register_playlist(&params.url, segments, &headers_param);
+                        }
+
+                        proxy_uris(&mut playlist, &endpoint("proxy"), &headers_param);
+                        playlist.to_string()
+                    },
+                    Err(e) => {
+                        println!("PROXY: Passing through unparseable playlist {}: {}", params.url, e);
+                        body_text.clone()
+                    },
+                };
+
                 println!("PROXY: Rewritten m3u8 ({} bytes -> {} bytes)", body_text.len(), rewritten.len());
-                
+
                 let body = warp::hyper::Body::from(rewritten);
                 let mut reply = warp::reply::Response::new(body);
                 *reply.status_mut() = warp::hyper::StatusCode::from_u16(status.as_u16()).unwrap_or(warp::hyper::StatusCode::INTERNAL_SERVER_ERROR);
                 
-                // Add CORS and content-type headers
-                reply.headers_mut().insert("access-control-allow-origin", "*".parse().unwrap());
-                reply.headers_mut().insert("access-control-allow-methods", "GET, POST, OPTIONS, HEAD".parse().unwrap());
-                reply.headers_mut().insert("access-control-allow-headers", "*".parse().unwrap());
                 reply.headers_mut().insert("content-type", "application/vnd.apple.mpegurl".parse().unwrap());
                 
                 Ok(reply)
+            } else if cacheable && status.is_success() {
+                // Segments are small enough to buffer, cache and then serve
+                let body = match response.bytes().await {
+                    Ok(body) => body,
+                    Err(e) => {
+                        eprintln!("Proxy Request Failed: {}", e);
+                        return Err(warp::reject::not_found());
+                    },
+                };
+                let content_type = headers.get("content-type").and_then(|v| v.to_str().ok()).map(str::to_string);
+                let segment = CachedSegment { body, content_type };
+                segment_cache::cache().put(&params.url, segment.clone());
+                spawn_prefetch(&params.url);
+                Ok(segment_reply(&params.url, segment))
             } else {
                 // For non-m3u8 files, stream directly
                 let stream = response.bytes_stream().map(|result| {
@@ -161,20 +353,10 @@ async fn handle_proxy_request(params: ProxyParams) -> Result<impl warp::Reply, w
                 let mut reply = warp::reply::Response::new(body);
                 *reply.status_mut() = warp::hyper::StatusCode::from_u16(status.as_u16()).unwrap_or(warp::hyper::StatusCode::INTERNAL_SERVER_ERROR);
 
-                // Add CORS headers
-                reply.headers_mut().insert("access-control-allow-origin", "*".parse().unwrap());
-                reply.headers_mut().insert("access-control-allow-methods", "GET, POST, OPTIONS, HEAD".parse().unwrap());
-                reply.headers_mut().insert("access-control-allow-headers", "*".parse().unwrap());
-
-                // Set content-type
+                // Length, Content-Range and Accept-Ranges keep 206 responses seekable
+                copy_headers(&mut reply, &headers, PASSTHROUGH_HEADERS);
                 if params.url.contains(".ts") {
                     reply.headers_mut().insert("content-type", "video/mp2t".parse().unwrap());
-                } else if let Some(content_type) = headers.get("content-type") {
-                     reply.headers_mut().insert("content-type", content_type.clone());
-                }
-                
-                if let Some(content_length) = headers.get("content-length") {
-                    reply.headers_mut().insert("content-length", content_length.clone());
                 }
 
                 Ok(reply)
@@ -187,56 +369,182 @@ async fn handle_proxy_request(params: ProxyParams) -> Result<impl warp::Reply, w
     }
 }
 
-// Helper function to rewrite URI= attributes in m3u8 tags
-fn rewrite_uri_in_tag(line: &str, origin: &str, base_url: &str, proxy_base: &str, headers_param: &str) -> String {
-    use regex::Regex;
-    let re = Regex::new(r#"URI="([^"]+)""#).unwrap();
-    re.replace_all(line, |caps: &regex::Captures| {
-        let uri = &caps[1];
-        // Convert to absolute URL first
-        let absolute_url = if uri.starts_with("http://") || uri.starts_with("https://") {
-            uri.to_string()
-        } else if uri.starts_with('/') {
-            format!("{}{}", origin, uri)
+fn copy_headers(reply: &mut warp::reply::Response, upstream: &reqwest::header::HeaderMap, names: &[&'static str]) {
+    for name in names {
+        if let Some(value) = upstream.get(*name) {
+            if let Ok(value) = warp::http::HeaderValue::from_bytes(value.as_bytes()) {
+                reply.headers_mut().insert(*name, value);
+            }
+        }
+    }
+}
+
+fn segment_reply(url: &str, segment: CachedSegment) -> warp::reply::Response {
+    let content_type = if url.contains(".ts") {
+        Some("video/mp2t".to_string())
+    } else {
+        segment.content_type
+    };
+    let len = segment.body.len();
+    let mut reply = warp::reply::Response::new(warp::hyper::Body::from(segment.body));
+    reply.headers_mut().insert("content-length", len.into());
+    if let Some(value) = content_type.and_then(|c| warp::http::HeaderValue::from_str(&c).ok()) {
+        reply.headers_mut().insert("content-type", value);
+    }
+    reply
+}
+
+/// Fetch the segments after `url` into the cache in the background
+fn spawn_prefetch(url: &str) {
+    for (target, headers_json) in segment_cache::cache().prefetch_after(url) {
+        tokio::spawn(async move {
+            let segment = prefetch_segment(&target, &headers_json).await;
+            if segment.is_none() {
+                println!("PROXY: Prefetch of {} failed", target);
+            }
+            segment_cache::cache().prefetch_done(&target, segment);
+        });
+    }
+}
+
+async fn prefetch_segment(url: &str, headers_json: &str) -> Option<CachedSegment> {
+    proxy_guard::check_target(url).await.ok()?;
+    let mut request = http::client().get_streaming(url);
+    if let Ok(headers_map) = serde_json::from_str::<HashMap<String, String>>(headers_json) {
+        for (key, value) in headers_map {
+            request = request.header(key, value);
+        }
+    }
+    let response = request.send_with_retry("proxy").await.ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    let content_type = response.headers().get("content-type").and_then(|v| v.to_str().ok()).map(str::to_string);
+    let body = response.bytes().await.ok()?;
+    Some(CachedSegment { body, content_type })
+}
+
+/// Send every (already absolute) HTTP URI in a playlist back through the proxy; `data:`
+/// and `skd:` key URIs are left for the player
+fn proxy_uris(playlist: &mut hls::Playlist, proxy_base: &str, headers_param: &str) {
+    playlist.map_uris(|uri| {
+        if uri.starts_with("http://") || uri.starts_with("https://") {
+            proxy_url_at(proxy_base, uri, headers_param)
         } else {
-            format!("{}{}", base_url, uri)
-        };
-        // Wrap through proxy
-        format!(
-            "URI=\"{}?url={}&headers={}\"",
-            proxy_base,
-            urlencoding::encode(&absolute_url),
-            urlencoding::encode(headers_param)
-        )
-    }).to_string()
+            uri.to_string()
+        }
+    });
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[tokio::test]
+    async fn test_ranges_and_heads_pass_through() {
+        use std::sync::Mutex;
+        use warp::http::{Method, StatusCode};
+
+        // Upstream records (method, path, Range) and serves a 1000-byte file and a playlist
+        static SEEN: Mutex<Vec<(Method, String, Option<String>)>> = Mutex::new(Vec::new());
+        let upstream = warp::method()
+            .and(warp::path::full())
+            .and(warp::header::optional::<String>("range"))
+            .map(|method: Method, path: warp::path::FullPath, range: Option<String>| {
+                SEEN.lock().unwrap().push((method, path.as_str().to_string(), range.clone()));
+                let reply = if path.as_str().ends_with(".m3u8") {
+                    warp::http::Response::builder().body("#EXTM3U\n#EXTINF:10,\nseg0.ts\n#EXT-X-ENDLIST\n".to_string())
+                } else if range.as_deref() == Some("bytes=100-199") {
+                    warp::http::Response::builder()
+                        .status(StatusCode::PARTIAL_CONTENT)
+                        .header("content-range", "bytes 100-199/1000")
+                        .header("accept-ranges", "bytes")
+                        .body("x".repeat(100))
+                } else {
+                    warp::http::Response::builder().header("accept-ranges", "bytes").body("x".repeat(1000))
+                };
+                reply.unwrap()
+            });
+        let (addr, serving) = warp::serve(upstream).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(serving);
+
+        let client = http::HttpClient::with_guard(http::HttpConfig::default(), |_| false);
+        let params = |path: &str| ProxyParams { url: format!("http://{}{}", addr, path), headers: None, quality: None, sig: None };
+        let body = |reply: warp::reply::Response| async { warp::hyper::body::to_bytes(reply.into_body()).await.unwrap() };
+
+        let reply = forward(Method::GET, Some("bytes=100-199".to_string()), params("/ep1.mp4"), &client).await.unwrap();
+        assert_eq!(reply.status(), StatusCode::PARTIAL_CONTENT);
+        let headers = reply.headers().clone();
+        assert_eq!(headers["content-range"], "bytes 100-199/1000");
+        assert_eq!(headers["accept-ranges"], "bytes");
+        assert_eq!(headers["content-length"], "100");
+        assert_eq!(body(reply).await.len(), 100);
+
+        let reply = forward(Method::HEAD, None, params("/ep1.mp4"), &client).await.unwrap();
+        assert_eq!(reply.status(), StatusCode::OK);
+        assert_eq!(reply.headers()["content-length"], "1000");
+        assert!(body(reply).await.is_empty());
+
+        let reply = forward(Method::GET, Some("bytes=0-".to_string()), params("/ep1/index.m3u8"), &client).await.unwrap();
+        assert!(String::from_utf8(body(reply).await.to_vec()).unwrap().contains("url=http%3A%2F%2F127.0.0.1"));
+
+        let seen = SEEN.lock().unwrap().clone();
+        assert_eq!(seen, vec![
+            (Method::GET, "/ep1.mp4".to_string(), Some("bytes=100-199".to_string())),
+            (Method::HEAD, "/ep1.mp4".to_string(), None),
+            (Method::GET, "/ep1/index.m3u8".to_string(), None),
+        ]);
+    }
+
     #[test]
     fn test_rewrite_m3u8_logic() {
-        let origin = "https://example.com";
-        let base_url = "https://example.com/hls/";
+        let playlist_url = reqwest::Url::parse("https://example.com/hls/index.m3u8").unwrap();
         let proxy_base = "http://localhost:3030/proxy";
         let headers_json = r#"{"Referer":"https://ref.com"}"#;
+        let rewrite = |body: &str| {
+            let mut playlist = hls::Playlist::parse(&format!("#EXTM3U\n{}\n", body)).unwrap();
+            playlist.resolve_uris(&playlist_url);
+            proxy_uris(&mut playlist, proxy_base, headers_json);
+            playlist.to_string()
+        };
 
         // Test 1: Absolute URL in URI tag
-        let input = r#"#EXT-X-media:TYPE=AUDIO,URI="https://other.com/audio.m3u8""#;
-        let output = rewrite_uri_in_tag(input, origin, base_url, proxy_base, headers_json);
+        let output = rewrite(r#"#EXT-X-media:TYPE=AUDIO,URI="https://other.com/audio.m3u8""#);
         assert!(output.contains(proxy_base));
         assert!(output.contains("url=https%3A%2F%2Fother.com%2Faudio.m3u8"));
         assert!(output.contains("headers=%7B%22Referer%22%3A%22https%3A%2F%2Fref.com%22%7D"));
 
         // Test 2: Relative URL (no slash)
-        let input_rel = r#"#EXT-X-media:URI="720p.m3u8""#;
-        let output_rel = rewrite_uri_in_tag(input_rel, origin, base_url, proxy_base, headers_json);
+        let output_rel = rewrite(r#"#EXT-X-media:URI="720p.m3u8""#);
         assert!(output_rel.contains("url=https%3A%2F%2Fexample.com%2Fhls%2F720p.m3u8"));
 
         // Test 3: Relative URL (with slash)
-        let input_slash = r#"#EXT-X-media:URI="/root/720p.m3u8""#;
-        let output_slash = rewrite_uri_in_tag(input_slash, origin, base_url, proxy_base, headers_json);
+        let output_slash = rewrite(r#"#EXT-X-media:URI="/root/720p.m3u8""#);
         assert!(output_slash.contains("url=https%3A%2F%2Fexample.com%2Froot%2F720p.m3u8"));
+
+        // Test 4: Parent paths, query strings and data: keys
+        let output_media = rewrite("#EXT-X-KEY:METHOD=AES-128,URI=\"data:text/plain;base64,AAAA\"\n#EXTINF:4,\n../seg/1.ts?t=a%26b");
+        assert!(output_media.contains("url=https%3A%2F%2Fexample.com%2Fseg%2F1.ts%3Ft%3Da%2526b"));
+        assert!(output_media.contains("URI=\"data:text/plain;base64,AAAA\""));
+    }
+
+    #[test]
+    fn test_proxy_urls_round_trip_and_rebase() {
+        let url = proxy_url("https://cdn.example/ep 1.m3u8?token=a&b=c", r#"{"Referer":"https://ref.com"}"#);
+        assert!(url.starts_with(&format!("{}/proxy?url=", proxy_base())));
+
+        let (target, headers) = proxy_target(&url).unwrap();
+        assert_eq!(target, "https://cdn.example/ep 1.m3u8?token=a&b=c");
+        assert_eq!(headers.get("Referer").map(String::as_str), Some("https://ref.com"));
+
+        // Earlier runs' URLs move to this run's base and key
+        let from_last_run = "http://127.0.0.1:49152/proxy?url=https%3A%2F%2Fcdn.example%2Fm.m3u8&headers=%7B%7D&sig=00&quality=720p";
+        assert_eq!(rebase(from_last_run), format!("{}&quality=720p", proxy_url("https://cdn.example/m.m3u8", "{}")));
+        assert!(proxy_guard::verify("https://cdn.example/m.m3u8", "{}", &url_param(&rebase(from_last_run), "sig")));
+        assert_eq!(rebase("https://cdn.example/a.mp4"), "https://cdn.example/a.mp4");
+    }
+
+    fn url_param(url: &str, name: &str) -> String {
+        reqwest::Url::parse(url).unwrap().query_pairs().find(|(k, _)| k == name).unwrap().1.into_owned()
     }
 }
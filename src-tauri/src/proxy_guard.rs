@@ -0,0 +1,322 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, OnceLock};
+use hmac::{Hmac, Mac};
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::redirect::{Attempt, Policy};
+use reqwest::{ClientBuilder, Url};
+use sha2::Sha256;
+use warp::hyper::client::connect::dns::Name;
+use crate::error::{NezukoError, Result};
+
+// ============================================================
+// Stream proxy lockdown
+// ------------------------------------------------------------
+// The proxy only fetches URLs this process built: every proxy URL
+// carries an HMAC of its target and headers under a key generated
+// at startup, so a page or LAN peer can't turn it into an open
+// proxy. Targets resolving to loopback/private/link-local addresses
+// are refused unless NEZUKO_PROXY_ALLOW_PRIVATE is set, on every
+// redirect hop and by the resolver the connection itself uses, so
+// a rebinding DNS answer can't slip past the check. CORS only
+// admits the app's own origins.
+// ============================================================
+
+const PROVIDER: &str = "proxy";
+
+/// Redirect hops followed for one proxied request
+const MAX_REDIRECTS: usize = 10;
+
+/// Origins the app's webview runs under (Vite dev server, Tauri on macOS/Linux, Tauri on Windows)
+const APP_ORIGINS: &[&str] = &["http://localhost:1420", "tauri://localhost", "http://tauri.localhost", "https://tauri.localhost"];
+
+#[derive(Debug, Clone)]
+pub struct ProxyConfig {
+    pub bind: IpAddr,
+    pub port: u16,
+    pub allow_private: bool,
+    pub allowed_origins: Vec<String>,
+    /// Remove injected ad runs from media playlists (see `adfilter`)
+    pub strip_ads: bool,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            bind: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 3030,
+            allow_private: false,
+            allowed_origins: APP_ORIGINS.iter().map(|o| o.to_string()).collect(),
+            strip_ads: true,
+        }
+    }
+}
+
+impl ProxyConfig {
+    /// Defaults, plus overrides from the environment: `NEZUKO_PROXY_BIND` (e.g. "0.0.0.0"),
+    /// `NEZUKO_PROXY_PORT`, `NEZUKO_PROXY_ALLOW_PRIVATE` ("1"), `NEZUKO_PROXY_ORIGINS`
+    /// (comma-separated extra CORS origins) and `NEZUKO_PROXY_STRIP_ADS` ("0" to disable).
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Ok(bind) = std::env::var("NEZUKO_PROXY_BIND") {
+            match bind.parse() {
+                Ok(ip) => config.bind = ip,
+                Err(e) => println!("DEBUG: Ignoring NEZUKO_PROXY_BIND: {}", e),
+            }
+        }
+        if let Some(port) = std::env::var("NEZUKO_PROXY_PORT").ok().and_then(|p| p.parse().ok()) {
+            config.port = port;
+        }
+        if let Ok(allow) = std::env::var("NEZUKO_PROXY_ALLOW_PRIVATE") {
+            config.allow_private = matches!(allow.as_str(), "1" | "true" | "yes");
+        }
+        if let Ok(strip) = std::env::var("NEZUKO_PROXY_STRIP_ADS") {
+            config.strip_ads = !matches!(strip.as_str(), "0" | "false" | "no");
+        }
+        if let Ok(origins) = std::env::var("NEZUKO_PROXY_ORIGINS") {
+            for origin in origins.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+                match parse_origin(origin) {
+                    Some(origin) => config.allowed_origins.push(origin),
+                    None => println!("DEBUG: Ignoring NEZUKO_PROXY_ORIGINS entry '{}': expected scheme://host[:port]", origin),
+                }
+            }
+        }
+        config
+    }
+}
+
+/// `scheme://host[:port]` in the form CORS compares against, or None for anything else
+/// (warp panics on origins it can't parse)
+fn parse_origin(origin: &str) -> Option<String> {
+    let url = Url::parse(origin).ok()?;
+    let host = url.host_str()?;
+    let bare = url.username().is_empty() && url.password().is_none()
+        && matches!(url.path(), "" | "/") && url.query().is_none() && url.fragment().is_none();
+    if !bare {
+        return None;
+    }
+    Some(match url.port() {
+        Some(port) => format!("{}://{}:{}", url.scheme(), host, port),
+        None => format!("{}://{}", url.scheme(), host),
+    })
+}
+
+pub fn config() -> &'static ProxyConfig {
+    static CONFIG: OnceLock<ProxyConfig> = OnceLock::new();
+    CONFIG.get_or_init(ProxyConfig::from_env)
+}
+
+// ============================================================
+// URL signing
+// ============================================================
+
+fn session_key() -> &'static [u8; 32] {
+    static KEY: OnceLock<[u8; 32]> = OnceLock::new();
+    KEY.get_or_init(rand::random)
+}
+
+fn mac(target: &str, headers: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(session_key()).expect("HMAC accepts any key length");
+    mac.update(target.as_bytes());
+    mac.update(b"\n");
+    mac.update(headers.as_bytes());
+    mac
+}
+
+/// Hex HMAC of a proxy target and its header JSON, valid for this run only
+pub fn sign(target: &str, headers: &str) -> String {
+    hex::encode(mac(target, headers).finalize().into_bytes())
+}
+
+/// Constant-time check of a `sig` from a proxy URL
+pub fn verify(target: &str, headers: &str, sig: &str) -> bool {
+    match hex::decode(sig) {
+        Ok(sig) => mac(target, headers).verify_slice(&sig).is_ok(),
+        Err(_) => false,
+    }
+}
+
+// ============================================================
+// Target checks (SSRF)
+// ============================================================
+
+/// Refuse non-HTTP targets and hosts that resolve to internal addresses
+pub async fn check_target(url: &str) -> Result<()> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| NezukoError::parse(PROVIDER, e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(NezukoError::unsupported(PROVIDER, format!("scheme '{}' is not proxied", parsed.scheme())));
+    }
+    if config().allow_private {
+        return Ok(());
+    }
+
+    let host = parsed.host_str().ok_or_else(|| NezukoError::parse(PROVIDER, "URL has no host"))?;
+    let port = parsed.port_or_known_default().unwrap_or(443);
+    // Literal IPs need no lookup; names are resolved so "internal.example" -> 10.x is caught too
+    let addrs: Vec<IpAddr> = match host.trim_start_matches('[').trim_end_matches(']').parse() {
+        Ok(ip) => vec![ip],
+        Err(_) => tokio::net::lookup_host((host, port)).await
+            .map_err(|e| NezukoError::network(PROVIDER, format!("resolve {}: {}", host, e)))?
+            .map(|a| a.ip())
+            .collect(),
+    };
+
+    match addrs.iter().find(|ip| is_internal(**ip)) {
+        Some(ip) => Err(NezukoError::unsupported(PROVIDER, format!("{} resolves to internal address {}", host, ip))),
+        None => Ok(()),
+    }
+}
+
+/// Whether the proxy must not connect to `ip` under the current config
+pub fn refused(ip: IpAddr) -> bool {
+    !config().allow_private && is_internal(ip)
+}
+
+/// Make a client builder safe for proxied fetches: names only resolve to addresses
+/// `refuse` accepts, and each redirect hop is checked before it is requested.
+pub fn guard(builder: ClientBuilder, refuse: fn(IpAddr) -> bool) -> ClientBuilder {
+    builder
+        .dns_resolver(Arc::new(GuardedResolver { refuse }))
+        .redirect(Policy::custom(move |attempt: Attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                return attempt.error("too many redirects");
+            }
+            match hop_refusal(attempt.url(), refuse) {
+                Some(reason) => {
+                    println!("PROXY: Refusing redirect to {}: {}", attempt.url(), reason);
+                    attempt.error(reason)
+                },
+                None => attempt.follow(),
+            }
+        }))
+}
+
+/// Why a redirect target can't be followed. Names are left to `GuardedResolver`; literal
+/// IPs never reach a resolver, so they are checked here.
+fn hop_refusal(url: &Url, refuse: fn(IpAddr) -> bool) -> Option<String> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Some(format!("scheme '{}' is not proxied", url.scheme()));
+    }
+    let ip = match url.host()? {
+        url::Host::Ipv4(v4) => IpAddr::V4(v4),
+        url::Host::Ipv6(v6) => IpAddr::V6(v6),
+        url::Host::Domain(_) => return None,
+    };
+    refuse(ip).then(|| format!("internal address {}", ip))
+}
+
+/// System DNS that fails when a name has a refused address. The connection is made to
+/// exactly the addresses checked here.
+struct GuardedResolver {
+    refuse: fn(IpAddr) -> bool,
+}
+
+impl Resolve for GuardedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let refuse = self.refuse;
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, 0)).await?.collect();
+            if let Some(addr) = addrs.iter().find(|a| refuse(a.ip())) {
+                return Err(format!("{} resolves to internal address {}", host, addr.ip()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Loopback, private, link-local, CGNAT, unspecified, broadcast/multicast, ULA
+pub fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            v4.is_private() || v4.is_loopback() || v4.is_link_local() || v4.is_unspecified()
+                || v4.is_broadcast() || v4.is_multicast() || a == 0
+                || (a == 100 && (64..128).contains(&b))
+        },
+        IpAddr::V6(v6) => {
+            let first = v6.segments()[0];
+            v6.is_loopback() || v6.is_unspecified() || v6.is_multicast()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+                || v6.to_ipv4_mapped().is_some_and(|v4| is_internal(IpAddr::V4(v4)))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signatures_bind_target_and_headers() {
+        let headers = r#"{"Referer":"https://megacloud.blog/"}"#;
+        let sig = sign("https://cdn.example/master.m3u8", headers);
+        assert!(verify("https://cdn.example/master.m3u8", headers, &sig));
+        assert!(!verify("http://192.168.1.1/admin", headers, &sig));
+        assert!(!verify("https://cdn.example/master.m3u8", r#"{"Cookie":"x"}"#, &sig));
+        assert!(!verify("https://cdn.example/master.m3u8", headers, "not-hex"));
+    }
+
+    #[test]
+    fn test_malformed_origins_are_skipped() {
+        assert_eq!(parse_origin("http://localhost:5173").as_deref(), Some("http://localhost:5173"));
+        assert_eq!(parse_origin("https://Nezuko.example/").as_deref(), Some("https://nezuko.example"));
+        assert_eq!(parse_origin("tauri://localhost").as_deref(), Some("tauri://localhost"));
+        for bad in ["localhost:5173", "localhost", "http://", "https://app.example/path", "http://user@app.example", "*"] {
+            assert_eq!(parse_origin(bad), None, "{} should be rejected", bad);
+        }
+        // What survives is accepted by warp
+        let valid: Vec<String> = ["http://localhost:5173/", "tauri://localhost", "localhost:5173"].iter().filter_map(|o| parse_origin(o)).collect();
+        let _ = warp::cors().allow_origins(valid.iter().map(String::as_str));
+    }
+
+    #[tokio::test]
+    async fn test_internal_targets_are_refused() {
+        for ip in ["127.0.0.1", "10.0.0.8", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:192.168.0.1"] {
+            assert!(is_internal(ip.parse().unwrap()), "{} should be internal", ip);
+        }
+        assert!(!is_internal("93.184.216.34".parse().unwrap()));
+        assert!(!is_internal("2606:4700::1111".parse().unwrap()));
+
+        assert!(check_target("http://169.254.169.254/latest/meta-data").await.is_err());
+        assert!(check_target("http://[::1]:8080/").await.is_err());
+        assert!(check_target("file:///etc/passwd").await.is_err());
+        assert!(check_target("https://93.184.216.34/video.mp4").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_redirects_to_internal_hosts_are_never_requested() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use warp::Filter;
+
+        // The "internal" server counts requests; the "public" one redirects to it. Loopback
+        // stands in for both, so only 127.0.0.2 counts as public here.
+        static INTERNAL_HITS: AtomicUsize = AtomicUsize::new(0);
+        let internal = warp::any().map(|| {
+            INTERNAL_HITS.fetch_add(1, Ordering::SeqCst);
+            "secret"
+        });
+        let (internal_addr, serving) = warp::serve(internal).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(serving);
+
+        let port = internal_addr.port();
+        let public = warp::path::param().map(move |to: String| {
+            let location = match to.as_str() {
+                "ip" => format!("http://127.0.0.1:{}/", port),
+                _ => format!("http://localhost:{}/", port),
+            };
+            warp::redirect::found(location.parse::<warp::http::Uri>().unwrap())
+        });
+        let (public_addr, serving) = warp::serve(public).bind_ephemeral(([127, 0, 0, 2], 0));
+        tokio::spawn(serving);
+
+        let client = guard(reqwest::Client::builder(), |ip| ip != IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2))).build().unwrap();
+        for to in ["ip", "name"] {
+            let result = client.get(format!("http://{}/{}", public_addr, to)).send().await;
+            assert!(result.is_err(), "redirect via {} was followed", to);
+        }
+        assert_eq!(INTERNAL_HITS.load(Ordering::SeqCst), 0);
+        assert!(reqwest::get(format!("http://{}/", internal_addr)).await.is_ok());
+        assert_eq!(INTERNAL_HITS.load(Ordering::SeqCst), 1);
+    }
+}
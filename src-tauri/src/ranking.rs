@@ -0,0 +1,184 @@
+use std::collections::HashSet;
+use crate::anime::AnimeResult;
+use crate::merge::{normalize_title, title_tokens};
+
+// ============================================================
+// Search relevance ranking
+// ------------------------------------------------------------
+// Every result is scored against the query: best fuzzy match over
+// its title and alternate titles (English / romaji / native), a
+// bonus for an exact or prefix match, a small bonus for long runs
+// (main series over spinoffs) and a penalty for recaps/specials
+// unless the query asks for one.
+// ============================================================
+
+/// Added when a title is exactly the query
+const EXACT_BONUS: f32 = 0.3;
+/// Added when a title starts with the query ("frieren" -> "Frieren: Beyond Journey's End")
+const PREFIX_BONUS: f32 = 0.15;
+/// Largest episode-count bonus, reached at `EPISODE_SATURATION` episodes
+const EPISODE_BONUS: f32 = 0.1;
+const EPISODE_SATURATION: f32 = 100.0;
+/// Subtracted from recap/special entries the user didn't ask for
+const SPECIAL_PENALTY: f32 = 0.3;
+const SPECIAL_MARKERS: &[&str] = &[
+    "recap", "recaps", "special", "specials", "ova", "oad", "summary", "compilation", "digest",
+];
+
+/// Score every result against `query` and sort best first. Ties keep provider order.
+pub fn rank(query: &str, mut results: Vec<AnimeResult>) -> Vec<AnimeResult> {
+    for result in results.iter_mut() {
+        result.relevance = Some(score(query, result));
+    }
+    results.sort_by(|a, b| b.relevance.unwrap_or(0.0).total_cmp(&a.relevance.unwrap_or(0.0)));
+    results
+}
+
+pub fn score(query: &str, result: &AnimeResult) -> f32 {
+    let query_norm = normalize_title(query);
+    let query_tokens = title_tokens(query);
+
+    let title_score = std::iter::once(&result.title)
+        .chain(&result.alt_titles)
+        .map(|title| title_score(&query_norm, &query_tokens, title))
+        .fold(0.0, f32::max);
+
+    let episodes = result.episode_count.unwrap_or(0).max(0) as f32;
+    let episode_bonus = EPISODE_BONUS * ((1.0 + episodes).ln() / (1.0 + EPISODE_SATURATION).ln()).min(1.0);
+
+    let penalty = if is_special(&result.title) && !is_special(query) { SPECIAL_PENALTY } else { 0.0 };
+
+    (title_score + episode_bonus - penalty).max(0.0)
+}
+
+fn title_score(query_norm: &str, query_tokens: &HashSet<String>, title: &str) -> f32 {
+    let title_norm = normalize_title(title);
+    if query_norm.is_empty() || title_norm.is_empty() {
+        return 0.0;
+    }
+
+    let mut score = fuzzy_similarity(query_tokens, &title_tokens(title));
+    if title_norm == query_norm {
+        score += EXACT_BONUS;
+    } else if title_norm.starts_with(query_norm) {
+        score += PREFIX_BONUS;
+    }
+    score
+}
+
+/// How much of the query the title covers, blended with how much of the title is
+/// the query, so "naruto" puts "Naruto" above "Naruto: Shippuden"
+fn fuzzy_similarity(query: &HashSet<String>, title: &HashSet<String>) -> f32 {
+    if query.is_empty() || title.is_empty() {
+        return 0.0;
+    }
+    let coverage = query.iter()
+        .map(|q| title.iter().map(|t| token_similarity(q, t)).fold(0.0, f32::max))
+        .sum::<f32>() / query.len() as f32;
+    let precision = title.iter()
+        .map(|t| query.iter().map(|q| token_similarity(q, t)).fold(0.0, f32::max))
+        .sum::<f32>() / title.len() as f32;
+    0.7 * coverage + 0.3 * precision
+}
+
+/// 1.0 for the same word, partial credit when the query word is a prefix
+/// ("shingeki" typed as "shinge") or a near spelling ("freiren")
+fn token_similarity(query_word: &str, title_word: &str) -> f32 {
+    if query_word == title_word {
+        return 1.0;
+    }
+    if query_word.chars().count() >= 3 && title_word.starts_with(query_word) {
+        return 0.9;
+    }
+    let dice = bigram_dice(query_word, title_word);
+    if dice >= 0.5 { dice * 0.8 } else { 0.0 }
+}
+
+/// Dice coefficient over character bigrams
+fn bigram_dice(a: &str, b: &str) -> f32 {
+    let bigrams = |s: &str| {
+        let chars: Vec<char> = s.chars().collect();
+        chars.windows(2).map(|w| (w[0], w[1])).collect::<Vec<_>>()
+    };
+    let (ba, mut bb) = (bigrams(a), bigrams(b));
+    if ba.is_empty() || bb.is_empty() {
+        return 0.0;
+    }
+
+    let total = ba.len() + bb.len();
+    let mut shared = 0;
+    for pair in &ba {
+        if let Some(pos) = bb.iter().position(|p| p == pair) {
+            bb.swap_remove(pos);
+            shared += 1;
+        }
+    }
+    2.0 * shared as f32 / total as f32
+}
+
+fn is_special(text: &str) -> bool {
+    title_tokens(text).iter().any(|t| SPECIAL_MARKERS.contains(&t.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ids::{MediaId, TranslationType};
+    use std::collections::HashMap;
+
+    fn result(title: &str, alt_titles: &[&str], episodes: i32) -> AnimeResult {
+        AnimeResult {
+            id: MediaId::HiAnime { slug: title.to_lowercase().replace(' ', "-"), category: TranslationType::Sub, dub_episodes: None },
+            title: title.to_string(),
+            url: String::new(),
+            image: String::new(),
+            release_date: None,
+            language: None,
+            is_multi_audio: false,
+            available_languages: Vec::new(),
+            provider: "hianime".to_string(),
+            episode_count: Some(episodes),
+            variant_ids: HashMap::new(),
+            alt_titles: alt_titles.iter().map(|t| t.to_string()).collect(),
+            matches: Vec::new(),
+            match_confidence: None,
+            relevance: None,
+            format: None,
+            rating: None,
+        }
+    }
+
+    fn titles(ranked: &[AnimeResult]) -> Vec<&str> {
+        ranked.iter().map(|r| r.title.as_str()).collect()
+    }
+
+    #[test]
+    fn test_exact_title_beats_sequels_and_recaps() {
+        let results = vec![
+            result("Naruto: Shippuden", &[], 500),
+            result("Naruto Recap Special", &[], 1),
+            result("Boruto: Naruto Next Generations", &[], 293),
+            result("Naruto", &[], 220),
+        ];
+        let ranked = rank("naruto", results);
+        assert_eq!(titles(&ranked), vec!["Naruto", "Naruto: Shippuden", "Boruto: Naruto Next Generations", "Naruto Recap Special"]);
+        assert!(ranked.iter().all(|r| r.relevance.is_some()));
+
+        // Asking for the special keeps it
+        let ranked = rank("naruto recap special", vec![result("Naruto", &[], 220), result("Naruto Recap Special", &[], 1)]);
+        assert_eq!(ranked[0].title, "Naruto Recap Special");
+    }
+
+    #[test]
+    fn test_alt_titles_and_typos_match() {
+        let results = vec![
+            result("Frieren: Beyond Journey's End Specials", &[], 2),
+            result("Spy x Family", &[], 25),
+            result("Frieren: Beyond Journey's End", &["Sousou no Frieren", "葬送のフリーレン"], 28),
+        ];
+        assert_eq!(rank("sousou no frieren", results.clone())[0].title, "Frieren: Beyond Journey's End");
+        assert_eq!(rank("葬送のフリーレン", results.clone())[0].title, "Frieren: Beyond Journey's End");
+
+        assert_eq!(rank("freiren", results)[0].title, "Frieren: Beyond Journey's End");
+    }
+}
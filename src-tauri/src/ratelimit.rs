@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::http::match_host;
+
+/// Token bucket settings for one upstream host
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    /// Requests that may go out back-to-back after an idle period
+    pub burst: u32,
+    /// Steady-state refill rate
+    pub per_second: f64,
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Token bucket per configured host. Hosts without a limit are never delayed
+/// (HLS segment CDNs go through the proxy and must not be throttled).
+pub struct HostLimiter {
+    limits: HashMap<String, RateLimit>,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl HostLimiter {
+    pub fn new(limits: HashMap<String, RateLimit>) -> Self {
+        Self { limits, buckets: Mutex::new(HashMap::new()) }
+    }
+
+    /// Take a token for `host` and return how long the caller must wait before sending.
+    /// Tokens may go negative: each waiter reserves its own slot, so requests leave in order.
+    fn reserve(&self, host: &str, now: Instant) -> Duration {
+        let Some((key, limit)) = match_host(&self.limits, host) else {
+            return Duration::ZERO;
+        };
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(key.clone()).or_insert(Bucket {
+            tokens: limit.burst as f64,
+            last_refill: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limit.per_second).min(limit.burst as f64);
+        bucket.last_refill = now;
+        bucket.tokens -= 1.0;
+
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / limit.per_second)
+        }
+    }
+
+    /// Wait until a request to `host` is allowed
+    pub async fn acquire(&self, host: &str) {
+        let wait = self.reserve(host, Instant::now());
+        if !wait.is_zero() {
+            println!("DEBUG: Rate limiting {} for {}ms", host, wait.as_millis());
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> HostLimiter {
+        let mut limits = HashMap::new();
+        limits.insert("hianime.bz".to_string(), RateLimit { burst: 2, per_second: 4.0 });
+        HostLimiter::new(limits)
+    }
+
+    #[test]
+    fn test_burst_then_throttle() {
+        let limiter = limiter();
+        let t0 = Instant::now();
+
+        assert_eq!(limiter.reserve("hianime.bz", t0), Duration::ZERO);
+        assert_eq!(limiter.reserve("hianime.bz", t0), Duration::ZERO);
+        // Bucket empty: next two queue up 250ms apart
+        assert_eq!(limiter.reserve("hianime.bz", t0), Duration::from_millis(250));
+        assert_eq!(limiter.reserve("hianime.bz", t0), Duration::from_millis(500));
+
+        // One second later the debt is repaid and one token is back
+        assert_eq!(limiter.reserve("hianime.bz", t0 + Duration::from_secs(1)), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_subdomains_share_bucket_and_unknown_hosts_pass() {
+        let limiter = limiter();
+        let t0 = Instant::now();
+
+        limiter.reserve("hianime.bz", t0);
+        limiter.reserve("www.hianime.bz", t0);
+        assert!(limiter.reserve("hianime.bz", t0) > Duration::ZERO);
+
+        for _ in 0..100 {
+            assert_eq!(limiter.reserve("cdn.example.net", t0), Duration::ZERO);
+        }
+    }
+}
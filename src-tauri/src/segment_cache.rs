@@ -0,0 +1,543 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use bytes::Bytes;
+use md5::{Digest, Md5};
+use serde::Serialize;
+
+// ============================================================
+// HLS segment cache for the stream proxy
+// ------------------------------------------------------------
+// Media playlists the proxy rewrites are registered here, so a
+// segment request is recognised and its successors are known.
+// Segments live in a byte-bounded LRU in memory; entries pushed
+// out spill to a second LRU on disk (cleared at startup, since
+// segment URLs are session-signed). Each stream keeps at most
+// `segments_per_stream` segments across both tiers, and at most
+// `max_streams` playlists stay registered. Fetching segment N
+// prefetches the next `prefetch_depth` segments.
+// ============================================================
+
+static CACHE: OnceLock<SegmentCache> = OnceLock::new();
+
+#[derive(Debug, Clone)]
+pub struct SegmentCacheConfig {
+    pub memory_bytes: u64,
+    /// 0 disables the disk tier
+    pub disk_bytes: u64,
+    pub segments_per_stream: usize,
+    /// Registered playlists kept; the least recently used goes first
+    pub max_streams: usize,
+    pub prefetch_depth: usize,
+}
+
+impl Default for SegmentCacheConfig {
+    fn default() -> Self {
+        Self {
+            memory_bytes: 256 * 1024 * 1024,
+            disk_bytes: 1024 * 1024 * 1024,
+            segments_per_stream: 400,
+            max_streams: 8,
+            prefetch_depth: 3,
+        }
+    }
+}
+
+impl SegmentCacheConfig {
+    /// Defaults, plus overrides from the environment: `NEZUKO_SEGMENT_CACHE_MB`,
+    /// `NEZUKO_SEGMENT_DISK_MB`, `NEZUKO_SEGMENTS_PER_STREAM`, `NEZUKO_SEGMENT_STREAMS`
+    /// and `NEZUKO_PREFETCH_DEPTH`.
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            std::env::var(name).ok()?.parse().ok()
+        }
+        let mut config = Self::default();
+        if let Some(mb) = var::<u64>("NEZUKO_SEGMENT_CACHE_MB") { config.memory_bytes = mb * 1024 * 1024; }
+        if let Some(mb) = var::<u64>("NEZUKO_SEGMENT_DISK_MB") { config.disk_bytes = mb * 1024 * 1024; }
+        if let Some(n) = var("NEZUKO_SEGMENTS_PER_STREAM") { config.segments_per_stream = n; }
+        if let Some(n) = var("NEZUKO_SEGMENT_STREAMS") { config.max_streams = n; }
+        if let Some(n) = var("NEZUKO_PREFETCH_DEPTH") { config.prefetch_depth = n; }
+        config
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CachedSegment {
+    pub body: Bytes,
+    pub content_type: Option<String>,
+}
+
+struct MemoryEntry {
+    segment: CachedSegment,
+    stream: String,
+    last_used: u64,
+}
+
+struct DiskEntry {
+    path: PathBuf,
+    size: u64,
+    content_type: Option<String>,
+    stream: String,
+    last_used: u64,
+}
+
+/// A registered media playlist and the segments of it that are cached
+struct Stream {
+    /// Segment URLs in playlist order
+    segments: Vec<String>,
+    headers: String,
+    /// Cached segments in either tier by last use, oldest first
+    cached: BTreeMap<u64, String>,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct State {
+    tick: u64,
+    memory: HashMap<String, MemoryEntry>,
+    memory_bytes: u64,
+    /// Memory entries by last use, oldest first
+    memory_lru: BTreeMap<u64, String>,
+    disk: HashMap<String, DiskEntry>,
+    disk_bytes: u64,
+    disk_lru: BTreeMap<u64, String>,
+    /// Keyed by playlist URL
+    streams: HashMap<String, Stream>,
+    /// Segment URL -> (stream, index in its playlist)
+    positions: HashMap<String, (String, usize)>,
+    prefetching: HashSet<String>,
+}
+
+impl State {
+    fn touch(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn take_memory(&mut self, url: &str) -> Option<MemoryEntry> {
+        let entry = self.memory.remove(url)?;
+        self.memory_lru.remove(&entry.last_used);
+        self.memory_bytes -= entry.segment.body.len() as u64;
+        Some(entry)
+    }
+
+    fn take_disk(&mut self, url: &str) -> Option<DiskEntry> {
+        let entry = self.disk.remove(url)?;
+        self.disk_lru.remove(&entry.last_used);
+        self.disk_bytes -= entry.size;
+        Some(entry)
+    }
+
+    /// Drop a segment from its stream's bookkeeping (not from the tiers)
+    fn forget(&mut self, stream: &str, last_used: u64) {
+        if let Some(stream) = self.streams.get_mut(stream) {
+            stream.cached.remove(&last_used);
+        }
+    }
+
+    /// Drop a segment from both tiers and its stream; returns the file to delete, if any
+    fn evict(&mut self, url: &str) -> Option<PathBuf> {
+        if let Some(entry) = self.take_memory(url) {
+            self.forget(&entry.stream, entry.last_used);
+        }
+        let entry = self.take_disk(url)?;
+        self.forget(&entry.stream, entry.last_used);
+        Some(entry.path)
+    }
+
+    /// Drop a stream with its playlist, positions and cached segments; returns files to delete
+    fn evict_stream(&mut self, key: &str) -> Vec<PathBuf> {
+        let Some(stream) = self.streams.remove(key) else { return Vec::new() };
+        for url in &stream.segments {
+            if self.positions.get(url).is_some_and(|(s, _)| s == key) {
+                self.positions.remove(url);
+            }
+        }
+        stream.cached.values().filter_map(|url| self.evict(url)).collect()
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SegmentCacheStats {
+    pub memory_entries: usize,
+    pub memory_bytes: u64,
+    pub disk_entries: usize,
+    pub disk_bytes: u64,
+    pub streams: usize,
+    pub hits: u64,
+    /// Hits served from the disk tier (included in `hits`)
+    pub disk_hits: u64,
+    pub misses: u64,
+    pub prefetched: u64,
+    pub hit_rate: Option<f64>,
+    pub prefetch_depth: usize,
+}
+
+pub struct SegmentCache {
+    config: SegmentCacheConfig,
+    disk_dir: Option<PathBuf>,
+    state: Mutex<State>,
+    hits: AtomicU64,
+    disk_hits: AtomicU64,
+    misses: AtomicU64,
+    prefetched: AtomicU64,
+}
+
+impl SegmentCache {
+    /// `disk_dir` is emptied: segments from an earlier run can't be matched to new URLs
+    pub fn new(config: SegmentCacheConfig, disk_dir: Option<PathBuf>) -> Self {
+        let disk_dir = disk_dir.filter(|_| config.disk_bytes > 0);
+        if let Some(dir) = &disk_dir {
+            let _ = fs::remove_dir_all(dir);
+            if let Err(e) = fs::create_dir_all(dir) {
+                println!("DEBUG: Could not create segment cache dir {}: {}", dir.display(), e);
+            }
+        }
+        Self {
+            config,
+            disk_dir,
+            state: Mutex::new(State::default()),
+            hits: AtomicU64::new(0),
+            disk_hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            prefetched: AtomicU64::new(0),
+        }
+    }
+
+    /// Remember a media playlist's segments (absolute upstream URLs). Live playlists
+    /// re-register on every reload; the newest list replaces the old one. Past
+    /// `max_streams`, the least recently used stream is dropped with its segments.
+    pub fn register_playlist(&self, playlist_url: &str, segments: Vec<String>, headers: &str) {
+        let mut delete = Vec::new();
+        {
+            let mut state = self.state.lock().unwrap();
+            let tick = state.touch();
+            let cached = match state.streams.remove(playlist_url) {
+                Some(old) => {
+                    for url in &old.segments {
+                        state.positions.remove(url);
+                    }
+                    old.cached
+                },
+                None => {
+                    // Few streams are registered at once, so a scan finds the oldest
+                    while state.streams.len() >= self.config.max_streams.max(1) {
+                        let Some(oldest) = state.streams.iter().min_by_key(|(_, s)| s.last_used).map(|(k, _)| k.clone()) else { break };
+                        println!("DEBUG: Segment cache dropping stream {}", oldest);
+                        delete.extend(state.evict_stream(&oldest));
+                    }
+                    BTreeMap::new()
+                },
+            };
+            for (i, url) in segments.iter().enumerate() {
+                state.positions.insert(url.clone(), (playlist_url.to_string(), i));
+            }
+            state.streams.insert(playlist_url.to_string(), Stream { segments, headers: headers.to_string(), cached, last_used: tick });
+        }
+        for path in delete {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// Whether `url` is a segment of a registered playlist (and so cacheable)
+    pub fn is_segment(&self, url: &str) -> bool {
+        self.state.lock().unwrap().positions.contains_key(url)
+    }
+
+    pub fn get(&self, url: &str) -> Option<CachedSegment> {
+        let from_disk = {
+            let mut state = self.state.lock().unwrap();
+            let tick = state.touch();
+            if let Some(mut entry) = state.take_memory(url) {
+                state.forget(&entry.stream, entry.last_used);
+                entry.last_used = tick;
+                let segment = entry.segment.clone();
+                self.insert_memory(&mut state, url, entry);
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Some(segment);
+            }
+            state.disk.get(url).map(|e| (e.path.clone(), e.content_type.clone()))
+        };
+
+        // Disk reads happen outside the lock; a hit moves the segment back to memory
+        let segment = from_disk.and_then(|(path, content_type)| {
+            fs::read(&path).ok().map(|body| CachedSegment { body: Bytes::from(body), content_type })
+        });
+        match segment {
+            Some(segment) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                self.disk_hits.fetch_add(1, Ordering::Relaxed);
+                self.put(url, segment.clone());
+                Some(segment)
+            },
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            },
+        }
+    }
+
+    fn insert_memory(&self, state: &mut State, url: &str, entry: MemoryEntry) {
+        if let Some(stream) = state.streams.get_mut(&entry.stream) {
+            stream.cached.insert(entry.last_used, url.to_string());
+            stream.last_used = entry.last_used;
+        }
+        state.memory_lru.insert(entry.last_used, url.to_string());
+        state.memory_bytes += entry.segment.body.len() as u64;
+        state.memory.insert(url.to_string(), entry);
+    }
+
+    /// Cache a segment of a registered playlist (others are ignored)
+    pub fn put(&self, url: &str, segment: CachedSegment) {
+        let size = segment.body.len() as u64;
+        if size > self.config.memory_bytes {
+            return;
+        }
+
+        let (spill, mut delete) = {
+            let mut state = self.state.lock().unwrap();
+            let Some((stream, _)) = state.positions.get(url).cloned() else { return };
+            let mut delete: Vec<PathBuf> = state.evict(url).into_iter().collect();
+            let tick = state.touch();
+            self.insert_memory(&mut state, url, MemoryEntry { segment, stream: stream.clone(), last_used: tick });
+
+            // Per-stream cap: drop that stream's oldest segment from whichever tier holds it
+            loop {
+                let oldest = state.streams.get_mut(&stream)
+                    .filter(|s| s.cached.len() > self.config.segments_per_stream)
+                    .and_then(|s| s.cached.pop_first());
+                let Some((_, key)) = oldest else { break };
+                delete.extend(state.evict(&key));
+            }
+
+            // Memory budget: least recently used segments move to disk. They leave their
+            // stream's bookkeeping until they are written.
+            let mut spill = Vec::new();
+            while state.memory_bytes > self.config.memory_bytes {
+                let Some(key) = state.memory_lru.first_key_value().map(|(_, k)| k.clone()) else { break };
+                let entry = state.take_memory(&key).unwrap();
+                state.forget(&entry.stream, entry.last_used);
+                if self.disk_dir.is_some() {
+                    spill.push((key, entry));
+                }
+            }
+            (spill, delete)
+        };
+
+        if let Some(dir) = &self.disk_dir {
+            let mut written = Vec::new();
+            for (key, entry) in spill {
+                let path = dir.join(hex::encode(Md5::digest(key.as_bytes())));
+                if fs::write(&path, &entry.segment.body).is_ok() {
+                    written.push((key, DiskEntry {
+                        path,
+                        size: entry.segment.body.len() as u64,
+                        content_type: entry.segment.content_type,
+                        stream: entry.stream,
+                        last_used: entry.last_used,
+                    }));
+                }
+            }
+
+            let mut state = self.state.lock().unwrap();
+            for (key, entry) in written {
+                // Skip segments cached again, or whose stream went away, while unlocked
+                let on_disk = state.disk.contains_key(&key);
+                let current = !on_disk && !state.memory.contains_key(&key);
+                match state.streams.get_mut(&entry.stream).filter(|_| current) {
+                    Some(stream) => {
+                        stream.cached.insert(entry.last_used, key.clone());
+                        state.disk_lru.insert(entry.last_used, key.clone());
+                        state.disk_bytes += entry.size;
+                        state.disk.insert(key, entry);
+                    },
+                    // The newer disk copy has the same file name
+                    None if on_disk => {},
+                    None => delete.push(entry.path),
+                }
+            }
+            while state.disk_bytes > self.config.disk_bytes {
+                let Some(key) = state.disk_lru.first_key_value().map(|(_, k)| k.clone()) else { break };
+                delete.extend(state.evict(&key));
+            }
+        }
+
+        for path in delete {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// The next `prefetch_depth` segments after `url` that are neither cached nor already
+    /// being fetched, with their headers. They are marked in flight until `prefetch_done`.
+    pub fn prefetch_after(&self, url: &str) -> Vec<(String, String)> {
+        let mut state = self.state.lock().unwrap();
+        let Some((stream, index)) = state.positions.get(url).cloned() else { return Vec::new() };
+        let Some(stream) = state.streams.get(&stream) else { return Vec::new() };
+
+        let targets: Vec<(String, String)> = stream.segments.iter()
+            .skip(index + 1)
+            .take(self.config.prefetch_depth)
+            .map(|next| (next.clone(), stream.headers.clone()))
+            .collect();
+        let targets: Vec<(String, String)> = targets.into_iter()
+            .filter(|(next, _)| !state.memory.contains_key(next) && !state.disk.contains_key(next) && !state.prefetching.contains(next))
+            .collect();
+        for (next, _) in &targets {
+            state.prefetching.insert(next.clone());
+        }
+        targets
+    }
+
+    pub fn prefetch_done(&self, url: &str, segment: Option<CachedSegment>) {
+        self.state.lock().unwrap().prefetching.remove(url);
+        if let Some(segment) = segment {
+            self.prefetched.fetch_add(1, Ordering::Relaxed);
+            self.put(url, segment);
+        }
+    }
+
+    pub fn stats(&self) -> SegmentCacheStats {
+        let state = self.state.lock().unwrap();
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        SegmentCacheStats {
+            memory_entries: state.memory.len(),
+            memory_bytes: state.memory_bytes,
+            disk_entries: state.disk.len(),
+            disk_bytes: state.disk_bytes,
+            streams: state.streams.len(),
+            hits,
+            disk_hits: self.disk_hits.load(Ordering::Relaxed),
+            misses,
+            prefetched: self.prefetched.load(Ordering::Relaxed),
+            hit_rate: (hits + misses > 0).then(|| hits as f64 / (hits + misses) as f64),
+            prefetch_depth: self.config.prefetch_depth,
+        }
+    }
+
+    /// Drop every cached segment (registered playlists are kept). Returns how many were removed.
+    pub fn clear(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let removed = state.memory.len() + state.disk.len();
+        for (_, entry) in state.disk.drain() {
+            let _ = fs::remove_file(entry.path);
+        }
+        for stream in state.streams.values_mut() {
+            stream.cached.clear();
+        }
+        state.memory.clear();
+        state.memory_lru.clear();
+        state.disk_lru.clear();
+        state.memory_bytes = 0;
+        state.disk_bytes = 0;
+        removed
+    }
+}
+
+/// Install the configuration and disk directory. Returns false if the cache was already created.
+pub fn init(config: SegmentCacheConfig, disk_dir: Option<PathBuf>) -> bool {
+    CACHE.set(SegmentCache::new(config, disk_dir)).is_ok()
+}
+
+/// The shared cache (memory-only with `SegmentCacheConfig::from_env()` if `init` wasn't called)
+pub fn cache() -> &'static SegmentCache {
+    CACHE.get_or_init(|| SegmentCache::new(SegmentCacheConfig::from_env(), None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(len: usize) -> CachedSegment {
+        CachedSegment { body: Bytes::from(vec![7u8; len]), content_type: Some("video/mp2t".to_string()) }
+    }
+
+    fn urls(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("https://cdn.example/ep1/seg{}.ts", i)).collect()
+    }
+
+    #[test]
+    fn test_lru_spills_to_disk_and_promotes_back() {
+        let dir = std::env::temp_dir().join(format!("nezuko-segments-test-{}", std::process::id()));
+        let config = SegmentCacheConfig { memory_bytes: 250, disk_bytes: 250, segments_per_stream: 100, max_streams: 4, prefetch_depth: 2 };
+        let cache = SegmentCache::new(config, Some(dir.clone()));
+        let segs = urls(6);
+        cache.register_playlist("https://cdn.example/ep1/index.m3u8", segs.clone(), "{}");
+
+        for url in &segs[..3] {
+            cache.put(url, segment(100));
+        }
+        // seg0 was least recently used and went to disk
+        let stats = cache.stats();
+        assert_eq!((stats.memory_entries, stats.disk_entries), (2, 1));
+
+        assert_eq!(cache.get(&segs[0]).unwrap().body.len(), 100);
+        assert!(cache.get(&segs[5]).is_none());
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.disk_hits, stats.misses), (1, 1, 1));
+        assert_eq!(stats.hit_rate, Some(0.5));
+
+        // Disk overflow drops the oldest entirely
+        for url in &segs[3..] {
+            cache.put(url, segment(100));
+        }
+        let stats = cache.stats();
+        assert!(stats.memory_bytes <= 250 && stats.disk_bytes <= 250);
+        assert_eq!(stats.memory_entries + stats.disk_entries, 4);
+
+        assert_eq!(cache.clear(), 4);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_prefetch_targets_follow_the_playlist() {
+        let config = SegmentCacheConfig { prefetch_depth: 2, segments_per_stream: 3, ..Default::default() };
+        let cache = SegmentCache::new(config, None);
+        let segs = urls(6);
+        cache.register_playlist("https://cdn.example/ep1/index.m3u8", segs.clone(), r#"{"Referer":"x"}"#);
+        assert!(cache.is_segment(&segs[2]) && !cache.is_segment("https://cdn.example/other.ts"));
+
+        cache.put(&segs[2], segment(10));
+        let targets = cache.prefetch_after(&segs[1]);
+        assert_eq!(targets, vec![(segs[3].clone(), r#"{"Referer":"x"}"#.to_string())]);
+        // Already in flight
+        assert!(cache.prefetch_after(&segs[1]).is_empty());
+
+        cache.prefetch_done(&segs[3], Some(segment(10)));
+        assert_eq!(cache.prefetch_after(&segs[4]), vec![(segs[5].clone(), r#"{"Referer":"x"}"#.to_string())]);
+        assert!(cache.prefetch_after(&segs[5]).is_empty());
+
+        // Per-stream cap keeps the three most recent
+        cache.put(&segs[4], segment(10));
+        cache.put(&segs[5], segment(10));
+        assert_eq!(cache.stats().memory_entries, 3);
+        assert!(cache.get(&segs[2]).is_none());
+        assert_eq!(cache.stats().prefetched, 1);
+    }
+
+    #[test]
+    fn test_stream_cap_drops_the_oldest_stream_whole() {
+        let config = SegmentCacheConfig { max_streams: 2, ..Default::default() };
+        let cache = SegmentCache::new(config, None);
+        let episodes: Vec<Vec<String>> = (1..=3)
+            .map(|ep| (0..3).map(|i| format!("https://cdn.example/ep{}/seg{}.ts", ep, i)).collect())
+            .collect();
+
+        for (ep, segs) in episodes.iter().enumerate().take(2) {
+            cache.register_playlist(&format!("https://cdn.example/ep{}/index.m3u8", ep + 1), segs.clone(), "{}");
+            cache.put(&segs[0], segment(10));
+        }
+        // ep1 was used more recently than ep2, so ep2 makes way for ep3
+        assert!(cache.get(&episodes[0][0]).is_some());
+        cache.register_playlist("https://cdn.example/ep3/index.m3u8", episodes[2].clone(), "{}");
+
+        let stats = cache.stats();
+        assert_eq!((stats.streams, stats.memory_entries), (2, 1));
+        assert!(!cache.is_segment(&episodes[1][0]) && cache.is_segment(&episodes[0][1]));
+        // A late prefetch for the dropped stream isn't cached
+        cache.prefetch_done(&episodes[1][1], Some(segment(10)));
+        assert_eq!(cache.stats().memory_entries, 1);
+    }
+}
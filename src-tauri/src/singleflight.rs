@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use futures::future::{BoxFuture, FutureExt, Shared};
+
+/// Collapses concurrent calls with the same key into one in-flight future.
+/// Every caller gets a clone of the single result; nothing is kept once it completes.
+pub struct SingleFlight<T: Clone> {
+    inflight: Mutex<HashMap<String, Shared<BoxFuture<'static, T>>>>,
+}
+
+impl<T: Clone + Send + Sync + 'static> Default for SingleFlight<T> {
+    fn default() -> Self {
+        Self { inflight: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> SingleFlight<T> {
+    /// Run `make()` unless a call for `key` is already in flight, in which case join it
+    pub async fn run<F, Fut>(&self, key: &str, make: F) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T> + Send + 'static,
+    {
+        let shared = {
+            let mut inflight = self.inflight.lock().unwrap();
+            match inflight.get(key) {
+                Some(existing) => {
+                    println!("DEBUG: Joining in-flight request '{}'", key);
+                    existing.clone()
+                },
+                None => {
+                    let fut = make().boxed().shared();
+                    inflight.insert(key.to_string(), fut.clone());
+                    fut
+                },
+            }
+        };
+
+        let result = shared.clone().await;
+
+        // Forget the finished call, unless a newer one already replaced it
+        let mut inflight = self.inflight.lock().unwrap();
+        if inflight.get(key).is_some_and(|current| current.ptr_eq(&shared)) {
+            inflight.remove(key);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_concurrent_calls_share_one_future() {
+        let flights: SingleFlight<usize> = SingleFlight::default();
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let make = || {
+            let calls = calls.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                calls.fetch_add(1, Ordering::SeqCst) + 1
+            }
+        };
+
+        let (a, b, c) = tokio::join!(
+            flights.run("episodes:x", make),
+            flights.run("episodes:x", make),
+            flights.run("episodes:y", make),
+        );
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Completed calls are not cached
+        flights.run("episodes:x", make).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert!(flights.inflight.lock().unwrap().is_empty());
+    }
+}
@@ -1,7 +1,10 @@
 
 import { useState, useEffect, useRef, useCallback } from "react";
 import { invoke } from "../lib/tauri";
-import type { AnimeMedia, Episode, AnimeResult, LanguageVariant, WatchContext, ProviderMappings, VideoSource } from "../types";
+import type { AnimeMedia, Episode, AnimeResult, BackendError, LanguageVariant, WatchContext, ProviderMappings, ProviderInfo, VideoSource } from "../types";
+
+/** Registry id of the regional (AnimeWorldIndia) provider */
+const REGIONAL_PROVIDER = "awi";
 
 // ===================================================================================
 // NEZUKOCHAN PROVIDER HOOK (STRICT SPEC)
@@ -94,7 +97,10 @@ export function useAnimeEpisodes(anime: AnimeMedia | null, context: WatchContext
         setProviderStatus("Loading episodes...");
         setLoading(true);
         try {
-            const epList = await invoke<Episode[]>("get_episodes_command", { id });
+            const epList = await invoke<Episode[]>("get_episodes_command", {
+                id,
+                anilistId: mappingsRef.current?.anilist_id ?? null,
+            });
             console.log(`[useProvider] Received ${epList.length} episodes for ${id}`);
 
             if (fetchIdRef.current !== currentFetchId) {
@@ -110,16 +116,19 @@ export function useAnimeEpisodes(anime: AnimeMedia | null, context: WatchContext
                     const epNumPadded = epNum.padStart(2, "0"); // Try "01" if "1" fails
 
 
-                    // 1. Try AniZip Mappings (Best for titles)
-                    let title = mappingsRef.current?.titles?.[epNum] ||
+                    // 1. Backend metadata (provider + AniZip), then raw AniZip mappings
+                    let title = ep.title ||
+                        mappingsRef.current?.titles?.[epNum] ||
                         mappingsRef.current?.titles?.[epNumPadded] ||
                         mappingsRef.current?.titles?.[ep.number];
 
-                    let description = mappingsRef.current?.overviews?.[epNum] ||
+                    let description = ep.synopsis ||
+                        mappingsRef.current?.overviews?.[epNum] ||
                         mappingsRef.current?.overviews?.[epNumPadded] ||
                         mappingsRef.current?.overviews?.[ep.number];
 
-                    let image = mappingsRef.current?.images?.[epNum] ||
+                    let image = ep.thumbnail ||
+                        mappingsRef.current?.images?.[epNum] ||
                         mappingsRef.current?.images?.[epNumPadded] ||
                         mappingsRef.current?.images?.[ep.number];
 
@@ -326,9 +335,18 @@ export function useAnimeEpisodes(anime: AnimeMedia | null, context: WatchContext
         const allResults: AnimeResult[] = [];
         const seenIds = new Set<string>();
 
+        // Regional searches go through the provider registry; skip them while it has no AWI provider
+        const providers = await invoke<ProviderInfo[]>("list_providers_command").catch(() => [] as ProviderInfo[]);
+        if (!providers.some(p => p.id === REGIONAL_PROVIDER)) {
+            console.log(`[useProvider] No "${REGIONAL_PROVIDER}" provider registered, skipping regional search`);
+            setRegionalAvailable(false);
+            if (context === "regional") throw new Error("No Regional sources found.");
+            return;
+        }
+
         // Fire all searches in parallel
         const searchPromises = queries.map(query =>
-            invoke<AnimeResult[]>("search_awi_command", { query }).catch(e => {
+            invoke<AnimeResult[]>("search_provider_command", { provider: REGIONAL_PROVIDER, query }).catch(e => {
                 console.warn(`AWI Search Error for "${query}":`, e);
                 return [] as AnimeResult[];
             })
@@ -597,16 +615,17 @@ function buildVariants(allResults: AnimeResult[], mode: "sub" | "dub", expectedE
     const diamonds = allResults.filter(r => r.provider === "allanime");
     const diamond = findBestMatch(diamonds, expectedEps);
     if (diamond) {
-        // AllAnime: append :sub or :dub suffix for backend to use correct mode
-        const allanimeId = diamond.id.replace(/:(?:sub|dub)$/, "") + ":" + mode;
+        // AllAnime: backend hands out one ID per audio mode
+        const allanimeId = diamond.variant_ids?.[mode] ?? diamond.id;
         newVariants.push({ name: "Server 1", id: allanimeId, type: mode === "dub" ? "dub" : "sub" });
     }
 
-    // Gold = Server 2 (HiAnime) — multi-audio, same ID for sub/dub
+    // Gold = Server 2 (HiAnime) — one show page, backend hands out one ID per audio category
     const golds = allResults.filter(r => r.provider === "hianime");
     const gold = findBestMatch(golds, expectedEps);
-    if (gold) {
-        newVariants.push({ name: "Server 2", id: gold.id, type: "multi" });
+    const goldId = gold?.variant_ids?.[mode];
+    if (goldId) {
+        newVariants.push({ name: "Server 2", id: goldId, type: mode });
     }
 
     // Silver = Server 3 (Anitaku) — separate entries for sub vs dub
@@ -671,7 +690,7 @@ export function useStream(episodeId: string | undefined) {
             .catch(err => {
                 if (mounted) {
                     console.error("[useStream] Failed to fetch stream:", err);
-                    setError(typeof err === 'string' ? err : "Failed to load stream");
+                    setError(typeof err === 'string' ? err : (err as BackendError)?.message ?? "Failed to load stream");
                     setIsLoading(false);
                 }
             });
@@ -185,6 +185,13 @@ export interface Episode {
   title?: string;
   description?: string;
   image?: string;
+  // Filled by the backend (provider list + AniZip when anilistId is passed)
+  synopsis?: string | null;
+  thumbnail?: string | null;
+  air_date?: string | null; // "2023-09-29"
+  runtime?: number | null; // minutes
+  is_filler?: boolean;
+  is_recap?: boolean;
 }
 
 export interface SubtitleTrack {
@@ -207,6 +214,73 @@ export interface VideoSource {
   audio_tracks?: AudioTrack[];
   provider: string; // "Diamond" | "Gold" | "Silver"
   provider_id: string; // "allanime" | "gogo" | "hianime"
+  server?: string; // "Luf-Mp4", "HD-1", "Filemoon"...
+  source_type?: string; // "hls" | "mp4"
+  status?: "resolved" | "failed";
+  error?: string | null;
+  /** Skippable ranges in seconds; also listed in `subtitles` as a "chapters" track */
+  segments?: SkipSegment[];
+  /** Renditions of the HLS master (get_stream_command lists them; pass `quality` to pin one) */
+  variants?: HlsVariant[];
+}
+
+export interface HlsVariant {
+  label: string; // "1080p"
+  uri: string;
+  bandwidth: number;
+  average_bandwidth?: number | null;
+  width?: number | null;
+  height?: number | null;
+  codecs?: string | null;
+  frame_rate?: number | null;
+  audio_group?: string | null;
+}
+
+export interface SkipSegment {
+  kind: "intro" | "outro" | "recap" | "preview";
+  start: number;
+  end: number;
+}
+
+/** Filters for search_allanime_command (values are AllAnime's GraphQL enums) */
+export interface AllAnimeSearchOptions {
+  page?: number;
+  limit?: number;
+  translation_type?: "sub" | "dub" | "raw";
+  country_origin?: "ALL" | "JP" | "CN" | "KR";
+  year?: number;
+  season?: "Winter" | "Spring" | "Summer" | "Fall";
+  genres?: string[];
+  sort_by?: "Recent" | "Top" | "Latest_Update" | "Name_ASC" | "Name_DESC";
+}
+
+export interface SearchPage {
+  results: AnimeResult[];
+  page: number;
+  has_next_page: boolean;
+  total_pages?: number;
+}
+
+/** One HiAnime playback server from get_hianime_servers; pass `id` as get_stream_command's serverId */
+export interface HiAnimeServer {
+  id: string;
+  name: string;
+  type: "sub" | "dub" | "raw";
+}
+
+/** Filters for search_hianime_command: the catalog filters plus an audio filter */
+export interface HiAnimeFilters extends CatalogFilters {
+  language?: "sub" | "dub";
+}
+
+/** One provider's entry inside a merged search result */
+export interface ProviderMatch {
+  provider: string;
+  id: string;
+  title: string;
+  languages: string[];
+  variant_ids: Record<string, string>;
+  episode_count?: number;
 }
 
 export interface AnimeResult {
@@ -225,10 +299,30 @@ export interface AnimeResult {
   // Episode count for sorting (AllAnime provides this)
   episode_count?: number;
 
+  // Backend-issued ID per audio mode ("sub" / "dub")
+  variant_ids?: Record<string, string>;
+  alt_titles?: string[];
+  // Set by search_anime_merged_command
+  matches?: ProviderMatch[];
+  match_confidence?: number;
+  // Search ranking score, higher is better
+  relevance?: number;
+  format?: string; // "TV", "Movie", "OVA"... when the provider lists it
+  rating?: string; // age rating badge, e.g. "18+"
+
   regionalAvailable?: boolean; // Computed or extra
   regionalLanguages?: string[];
 }
 
+// Error returned by every backend command (serialised NezukoError)
+export interface BackendError {
+  kind: "network" | "http_status" | "blocked" | "parse" | "decrypt" | "not_found" | "unsupported" | "unavailable";
+  provider: string;
+  message: string;
+  status: number | null;
+  retryable: boolean;
+}
+
 export type WatchContext = "global" | "regional";
 export type ActiveProvider = "anitaku" | "allanime" | "hianime";
 
@@ -248,6 +342,21 @@ export interface UnifiedAnimeResult {
   availableLanguages: string[];
 }
 
+// get_stream_for_anilist result: the source plus where it came from
+export interface ResolvedStream extends VideoSource {
+  served_by: ActiveProvider;
+  media_id: string;
+  episode_id: string;
+  skipped: string[];
+}
+
+/** Registered backend provider (`list_providers_command`) */
+export interface ProviderInfo {
+  id: string;   // "allanime" | "hianime" | "anitaku"
+  name: string;
+  tier: string; // "Diamond" | "Gold" | "Silver"
+}
+
 export interface ProviderMappings {
   anilist_id: number;
   mal_id?: number;
@@ -257,6 +366,9 @@ export interface ProviderMappings {
   zoro_id?: string;
   episode_count?: number;
   titles?: Record<string, string>;
+  show_titles?: Record<string, string>;
   overviews?: Record<string, string>;
   images?: Record<string, string>;
+  air_dates?: Record<string, string>;
+  runtimes?: Record<string, number>;
 }
@@ -45,10 +45,15 @@ export default defineConfig(async () => ({
     // 4. Proxy API requests to avoid CORS issues
     proxy: {
       '/api/proxy': {
-        target: 'http://localhost:3030',
+        target: 'http://127.0.0.1:3030',
         changeOrigin: true,
         rewrite: (path) => path.replace(/^\/api\/proxy/, '/proxy'),
       },
+      '/api/chapters': {
+        target: 'http://127.0.0.1:3030',
+        changeOrigin: true,
+        rewrite: (path) => path.replace(/^\/api\/chapters/, '/chapters'),
+      },
     },
   },
 }));